@@ -1,8 +1,16 @@
 import type { InitOutput } from "diff-wasm";
 import init, {
+	build_diff_tree_for_archives,
 	build_diff_tree_for_package,
+	check_go_sum,
+	clear_extraction_cache,
+	dispose_diff_session,
+	get_cache_stats,
 	get_diff_for_path,
+	get_package_patch,
 	prefetch_package,
+	set_extraction_limits,
+	set_registry_config,
 } from "diff-wasm";
 
 import wasmUrl from "diff-wasm/diff_wasm_bg.wasm?url";
@@ -25,18 +33,150 @@ export type DiffStatus =
 	| "removed"
 	| "modified"
 	| "unchanged"
-	| "renamed";
+	| "renamed"
+	| "mode_changed"
+	| "link_changed";
+
+export type RegistryCredentials =
+	| { type: "bearer"; token: string }
+	| { type: "basic"; username: string; password: string }
+	| { type: "token"; token: string };
+
+export type RegistryConfig = {
+	baseUrl?: string;
+	auth?: RegistryCredentials;
+	scopes?: Record<string, { baseUrl?: string; authToken?: string }>;
+};
+
+/**
+ * A suspicious entry name in the archive, e.g. `../../x` or `/etc/x`. For
+ * `link_escape` and `dangling_link`, `rawPath` is the link target.
+ */
+export type PathAnomaly = {
+	kind:
+		| "absolute"
+		| "traversal"
+		| "nul_byte"
+		| "duplicate"
+		| "link_escape"
+		| "dangling_link";
+	rawPath: string;
+};
+
+/** Caps against decompression bombs; omitted fields keep their defaults. */
+export type ExtractionLimits = {
+	maxCompressedBytes?: number;
+	maxTotalBytes?: number;
+	maxFileBytes?: number;
+	maxEntries?: number;
+	maxRatio?: number;
+};
 
 export type DiffFileEntry = {
 	path: string;
 	oldPath?: string;
-	type: "file" | "directory";
+	type: "file" | "directory" | "symlink";
 	status: DiffStatus;
+	binary?: boolean;
+	manifest?: boolean;
+	tooLarge?: boolean;
+	/** Permission bits, set only when execute or special bits changed. */
+	oldMode?: number;
+	newMode?: number;
+	/** Symlink target, or the file a hard link copies. */
+	linkTarget?: string;
+	oldLinkTarget?: string;
+	anomalies?: PathAnomaly[];
 	added?: number;
 	removed?: number;
 	children?: DiffFileEntry[];
 };
 
+export type Integrity = "verified" | "unverified" | "mismatch";
+
+export type SessionIntegrity = { from: Integrity; to: Integrity };
+
+/** Hashes as the ecosystem's lockfile pins them, e.g. Go's `h1:`. */
+export type LockfileHashes = { from: string | null; to: string | null };
+
+export type GoSumCheck = {
+	side: "from" | "to";
+	expected: string;
+	actual: string | null;
+	matches: boolean;
+};
+
+export type DiffLine = {
+	tag: "equal" | "insert" | "delete";
+	oldLine?: number;
+	newLine?: number;
+	content: string;
+	/** Last line of its file, without a trailing newline. */
+	noNewline?: boolean;
+	inline?: { start: number; end: number }[];
+};
+
+export type DiffHunk = {
+	oldStart: number;
+	oldLines: number;
+	newStart: number;
+	newLines: number;
+	lines: DiffLine[];
+};
+
+export type DiffErrorCode =
+	| "not_found"
+	| "network"
+	| "http_status"
+	| "unsupported_format"
+	| "corrupt"
+	| "limit_exceeded"
+	| "no_artifact"
+	| "invalid_argument";
+
+/** Shape of every error thrown by the wasm exports. */
+export type DiffError = {
+	code: DiffErrorCode;
+	message: string;
+	details?: { status: number; url: string };
+};
+
+function isDiffError(error: unknown): error is DiffError {
+	return (
+		typeof error === "object" &&
+		error !== null &&
+		"code" in error &&
+		"message" in error
+	);
+}
+
+/** Whether trying the same request again could succeed. */
+export function isRetryable(error: DiffError): boolean {
+	if (error.code === "network") return true;
+	if (error.code !== "http_status" || !error.details) return false;
+	const { status } = error.details;
+	return status === 408 || status === 429 || status >= 500;
+}
+
+/** Identifies what a reply answers; both ids are echoed when known. */
+type Reply = { requestId?: number; sessionId?: number };
+
+function postError(error: unknown, fallback: string, reply: Reply) {
+	if (isDiffError(error)) {
+		postMessage({
+			type: "error",
+			...reply,
+			error: error.message,
+			code: error.code,
+			details: error.details,
+			retryable: isRetryable(error),
+		});
+	} else {
+		const message = error instanceof Error ? error.message : fallback;
+		postMessage({ type: "error", ...reply, error: message });
+	}
+}
+
 type WorkerRequest =
 	| {
 			type: "start-diff";
@@ -45,6 +185,11 @@ type WorkerRequest =
 			from: string;
 			to: string;
 	  }
+	| {
+			type: "start-archive-diff";
+			from: Uint8Array;
+			to: Uint8Array;
+	  }
 	| {
 			type: "prefetch";
 			registry: string;
@@ -54,32 +199,117 @@ type WorkerRequest =
 	  }
 	| {
 			type: "get-diff";
+			sessionId: number;
 			filename: string;
 			oldPath?: string;
+			contextLines?: number;
+			inlineMode?: "word" | "char";
+	  }
+	| {
+			type: "get-patch";
+			sessionId: number;
+			contextLines?: number;
+			/** Include binary files as `GIT binary patch` literals. */
+			binaryLiterals?: boolean;
+	  }
+	| {
+			type: "check-go-sum";
+			sessionId: number;
+			line: string;
+	  }
+	| {
+			type: "dispose-session";
+			sessionId: number;
+	  }
+	| { type: "cache-stats" }
+	| { type: "clear-cache" }
+	| {
+			type: "set-registry-config";
+			registry: string;
+			config: RegistryConfig | null;
+	  }
+	| {
+			type: "set-extraction-limits";
+			limits: ExtractionLimits | null;
 	  };
 
-self.onmessage = async (event: MessageEvent<WorkerRequest>) => {
+/**
+ * `requestId` is echoed on every reply so callers can match results to their
+ * requests. Requests that return nothing are acknowledged with
+ * `{ type: "ok" }` once they have taken effect. Sessions are never disposed
+ * implicitly: whoever started one disposes it once it is superseded or no
+ * longer shown, so several views can keep sessions open side by side.
+ */
+type WorkerMessage = WorkerRequest & { requestId?: number };
+
+self.onmessage = async (event: MessageEvent<WorkerMessage>) => {
+	const data = event.data;
+	const { requestId } = data;
+
 	try {
 		await ensureWasmInitialized();
 	} catch (error) {
-		const message =
-			error instanceof Error ? error.message : "WASM initialization failed";
-		postMessage({ type: "error", error: message });
+		postError(error, "WASM initialization failed", { requestId });
 		return;
 	}
 
-	const data = event.data;
-
 	if (data.type === "start-diff") {
-		await handleStartDiff(data.registry, data.pkg, data.from, data.to);
+		await handleStartDiff(
+			requestId,
+			data.registry,
+			data.pkg,
+			data.from,
+			data.to,
+		);
+	} else if (data.type === "start-archive-diff") {
+		handleStartArchiveDiff(requestId, data.from, data.to);
 	} else if (data.type === "prefetch") {
 		await handlePrefetch(data.registry, data.pkg, data.from, data.to);
 	} else if (data.type === "get-diff") {
-		handleGetDiff(data.filename, data.oldPath);
+		handleGetDiff(
+			requestId,
+			data.sessionId,
+			data.filename,
+			data.oldPath,
+			data.contextLines,
+			data.inlineMode,
+		);
+	} else if (data.type === "get-patch") {
+		handleGetPatch(
+			requestId,
+			data.sessionId,
+			data.contextLines,
+			data.binaryLiterals,
+		);
+	} else if (data.type === "check-go-sum") {
+		handleCheckGoSum(requestId, data.sessionId, data.line);
+	} else if (data.type === "dispose-session") {
+		dispose_diff_session(data.sessionId);
+		postMessage({ type: "ok", requestId, sessionId: data.sessionId });
+	} else if (data.type === "cache-stats") {
+		postMessage({ type: "cache-stats", requestId, data: get_cache_stats() });
+	} else if (data.type === "clear-cache") {
+		clear_extraction_cache();
+		postMessage({ type: "cache-stats", requestId, data: get_cache_stats() });
+	} else if (data.type === "set-registry-config") {
+		try {
+			set_registry_config(data.registry, data.config);
+			postMessage({ type: "ok", requestId });
+		} catch (error) {
+			postError(error, "Unknown error", { requestId });
+		}
+	} else if (data.type === "set-extraction-limits") {
+		try {
+			set_extraction_limits(data.limits);
+			postMessage({ type: "ok", requestId });
+		} catch (error) {
+			postError(error, "Unknown error", { requestId });
+		}
 	}
 };
 
 async function handleStartDiff(
+	requestId: number | undefined,
 	registry: string,
 	pkg: string,
 	from: string,
@@ -87,24 +317,50 @@ async function handleStartDiff(
 ) {
 	try {
 		const start = performance.now();
-		const diffTree = await build_diff_tree_for_package(
-			registry,
-			pkg,
-			from,
-			to,
-			0.75,
-		);
+		const { sessionId, tree, integrity, lockfileHash } =
+			(await build_diff_tree_for_package(registry, pkg, from, to, 0.75)) as {
+				sessionId: number;
+				tree: DiffFileEntry;
+				integrity: SessionIntegrity;
+				lockfileHash?: LockfileHashes;
+			};
 		const end = performance.now();
 
 		console.log(`Building diff tree took ${(end - start).toFixed(2)}ms`);
 
 		postMessage({
 			type: "diff-result",
-			data: diffTree,
+			requestId,
+			sessionId,
+			data: tree,
+			integrity,
+			lockfileHash,
+		});
+	} catch (error) {
+		postError(error, "Unknown error", { requestId });
+	}
+}
+
+function handleStartArchiveDiff(
+	requestId: number | undefined,
+	from: Uint8Array,
+	to: Uint8Array,
+) {
+	try {
+		const { sessionId, tree } = build_diff_tree_for_archives(
+			from,
+			to,
+			0.75,
+		) as { sessionId: number; tree: DiffFileEntry };
+
+		postMessage({
+			type: "diff-result",
+			requestId,
+			sessionId,
+			data: tree,
 		});
 	} catch (error) {
-		const message = error instanceof Error ? error.message : "Unknown error";
-		postMessage({ type: "error", error: message });
+		postError(error, "Unknown error", { requestId });
 	}
 }
 
@@ -124,21 +380,65 @@ async function handlePrefetch(
 	}
 }
 
-export function handleGetDiff(filename: string, oldPath?: string) {
+export function handleGetDiff(
+	requestId: number | undefined,
+	sessionId: number,
+	filename: string,
+	oldPath?: string,
+	contextLines?: number,
+	inlineMode?: "word" | "char",
+) {
 	try {
-		const result = get_diff_for_path(filename, oldPath) as {
+		const result = get_diff_for_path(
+			sessionId,
+			filename,
+			oldPath,
+			contextLines,
+			inlineMode,
+		) as {
 			data: string;
 			isDiff: boolean;
+			binary: boolean;
+			hunks?: DiffHunk[];
 		};
 		postMessage({
 			type: "diff-result",
+			requestId,
+			sessionId,
 			filename,
 			data: result.data,
 			isDiff: result.isDiff,
+			binary: result.binary,
+			hunks: result.hunks,
 		});
 	} catch (error) {
-		const message =
-			error instanceof Error ? error.message : "Diff generation failed";
-		postMessage({ type: "error", error: message });
+		postError(error, "Diff generation failed", { requestId, sessionId });
+	}
+}
+
+export function handleGetPatch(
+	requestId: number | undefined,
+	sessionId: number,
+	contextLines?: number,
+	binaryLiterals?: boolean,
+) {
+	try {
+		const patch = get_package_patch(sessionId, contextLines, binaryLiterals);
+		postMessage({ type: "patch-result", requestId, sessionId, data: patch });
+	} catch (error) {
+		postError(error, "Patch generation failed", { requestId, sessionId });
+	}
+}
+
+export function handleCheckGoSum(
+	requestId: number | undefined,
+	sessionId: number,
+	line: string,
+) {
+	try {
+		const result = check_go_sum(sessionId, line) as GoSumCheck;
+		postMessage({ type: "go-sum-result", requestId, sessionId, data: result });
+	} catch (error) {
+		postError(error, "go.sum check failed", { requestId, sessionId });
 	}
 }
@@ -0,0 +1,302 @@
+use std::collections::HashMap;
+use std::mem::size_of;
+use std::rc::Rc;
+
+use serde::Serialize;
+
+use crate::integrity::Integrity;
+use diffpack_core::types::FileMapEntry;
+
+pub const DEFAULT_BUDGET_BYTES: usize = 256 * 1024 * 1024;
+
+/// Extracted packages keyed by `cache_key`, evicted least-recently-used once
+/// their estimated size exceeds the byte budget. Keys can be pinned (before or
+/// after they are inserted) by anything that reads them later, such as an open
+/// diff session; pinned entries are never evicted, so the budget is a soft limit
+/// while many comparisons are open. Packages are shared behind an `Rc`, so a
+/// hit hands out the cached extraction rather than a copy of it. Each package
+/// keeps the checks made on its raw archive, which go when it is evicted.
+pub struct ExtractionCache {
+    entries: HashMap<String, CacheEntry>,
+    pins: HashMap<String, usize>,
+    budget_bytes: usize,
+    total_bytes: usize,
+    clock: u64,
+    hits: u64,
+    misses: u64,
+    evictions: u64,
+}
+
+struct CacheEntry {
+    files: Rc<HashMap<String, FileMapEntry>>,
+    checks: ArchiveChecks,
+    size: usize,
+    last_used: u64,
+}
+
+/// What was learned from a package's raw archive before extraction, so it
+/// needs no second download to report.
+#[derive(Clone)]
+pub struct ArchiveChecks {
+    pub integrity: Integrity,
+    pub lockfile_hash: Option<String>,
+}
+
+impl Default for ArchiveChecks {
+    fn default() -> Self {
+        Self {
+            integrity: Integrity::Unverified,
+            lockfile_hash: None,
+        }
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CacheStats {
+    pub entries: usize,
+    pub pinned_entries: usize,
+    pub bytes: usize,
+    pub budget_bytes: usize,
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+}
+
+impl ExtractionCache {
+    pub fn new(budget_bytes: usize) -> Self {
+        Self {
+            entries: HashMap::new(),
+            pins: HashMap::new(),
+            budget_bytes,
+            total_bytes: 0,
+            clock: 0,
+            hits: 0,
+            misses: 0,
+            evictions: 0,
+        }
+    }
+
+    pub fn get(&mut self, key: &str) -> Option<&Rc<HashMap<String, FileMapEntry>>> {
+        self.clock += 1;
+        match self.entries.get_mut(key) {
+            Some(entry) => {
+                self.hits += 1;
+                entry.last_used = self.clock;
+                Some(&entry.files)
+            }
+            None => {
+                self.misses += 1;
+                None
+            }
+        }
+    }
+
+    /// Lookup that neither counts as a hit nor refreshes the entry's recency.
+    pub fn peek(&self, key: &str) -> Option<&HashMap<String, FileMapEntry>> {
+        self.entries.get(key).map(|entry| &*entry.files)
+    }
+
+    /// The checks stored with a package, without refreshing its recency.
+    pub fn checks(&self, key: &str) -> Option<&ArchiveChecks> {
+        self.entries.get(key).map(|entry| &entry.checks)
+    }
+
+    pub fn insert(
+        &mut self,
+        key: String,
+        files: Rc<HashMap<String, FileMapEntry>>,
+        checks: ArchiveChecks,
+    ) {
+        self.clock += 1;
+        let size = estimate_size(&files);
+        let entry = CacheEntry {
+            files,
+            checks,
+            size,
+            last_used: self.clock,
+        };
+        if let Some(previous) = self.entries.insert(key, entry) {
+            self.total_bytes -= previous.size;
+        }
+        self.total_bytes += size;
+        self.evict_to_budget();
+    }
+
+    pub fn pin(&mut self, key: &str) {
+        *self.pins.entry(key.to_string()).or_default() += 1;
+    }
+
+    pub fn unpin(&mut self, key: &str) {
+        if let Some(count) = self.pins.get_mut(key) {
+            *count -= 1;
+            if *count == 0 {
+                self.pins.remove(key);
+                self.evict_to_budget();
+            }
+        }
+    }
+
+    pub fn set_budget(&mut self, budget_bytes: usize) {
+        self.budget_bytes = budget_bytes;
+        self.evict_to_budget();
+    }
+
+    /// Drops every entry that is not pinned.
+    pub fn clear(&mut self) {
+        let pins = &self.pins;
+        let mut freed = 0;
+        self.entries.retain(|key, entry| {
+            let keep = pins.contains_key(key);
+            if !keep {
+                freed += entry.size;
+            }
+            keep
+        });
+        self.total_bytes -= freed;
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            entries: self.entries.len(),
+            pinned_entries: self
+                .entries
+                .keys()
+                .filter(|key| self.pins.contains_key(*key))
+                .count(),
+            bytes: self.total_bytes,
+            budget_bytes: self.budget_bytes,
+            hits: self.hits,
+            misses: self.misses,
+            evictions: self.evictions,
+        }
+    }
+
+    fn evict_to_budget(&mut self) {
+        while self.total_bytes > self.budget_bytes {
+            let victim = self
+                .entries
+                .iter()
+                .filter(|(key, _)| !self.pins.contains_key(*key))
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone());
+            let Some(victim) = victim else {
+                break;
+            };
+            if let Some(entry) = self.entries.remove(&victim) {
+                self.total_bytes -= entry.size;
+                self.evictions += 1;
+            }
+        }
+    }
+}
+
+/// Rough heap footprint of an extracted package: content, path strings and the
+/// per-entry map overhead.
+fn estimate_size(files: &HashMap<String, FileMapEntry>) -> usize {
+    files
+        .iter()
+        .map(|(path, entry)| {
+            path.len() + entry.bytes.len() + size_of::<String>() + size_of::<FileMapEntry>()
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn package() -> Rc<HashMap<String, FileMapEntry>> {
+        Rc::new(HashMap::from([(
+            "lib.rs".to_string(),
+            FileMapEntry::file(vec![b'x'; 1000]),
+        )]))
+    }
+
+    /// A cache with room for exactly `packages` of them.
+    fn cache_for(packages: usize) -> ExtractionCache {
+        ExtractionCache::new(packages * estimate_size(&package()))
+    }
+
+    fn keys(cache: &ExtractionCache) -> Vec<&str> {
+        let mut keys: Vec<&str> = cache.entries.keys().map(String::as_str).collect();
+        keys.sort();
+        keys
+    }
+
+    #[test]
+    fn evicts_the_least_recently_used_entry_at_capacity() {
+        let mut cache = cache_for(2);
+        cache.insert("a".to_string(), package(), ArchiveChecks::default());
+        cache.insert("b".to_string(), package(), ArchiveChecks::default());
+        assert!(cache.get("a").is_some());
+
+        cache.insert("c".to_string(), package(), ArchiveChecks::default());
+
+        assert_eq!(keys(&cache), ["a", "c"]);
+        assert_eq!(cache.stats().evictions, 1);
+        assert_eq!(cache.stats().bytes, 2 * estimate_size(&package()));
+    }
+
+    #[test]
+    fn peek_does_not_refresh_recency() {
+        let mut cache = cache_for(2);
+        cache.insert("a".to_string(), package(), ArchiveChecks::default());
+        cache.insert("b".to_string(), package(), ArchiveChecks::default());
+        assert!(cache.peek("a").is_some());
+
+        cache.insert("c".to_string(), package(), ArchiveChecks::default());
+
+        assert_eq!(keys(&cache), ["b", "c"]);
+    }
+
+    #[test]
+    fn pinned_entries_survive_eviction_and_clear() {
+        let mut cache = cache_for(1);
+        cache.pin("a");
+        cache.insert("a".to_string(), package(), ArchiveChecks::default());
+        cache.insert("b".to_string(), package(), ArchiveChecks::default());
+
+        // `b` is the newest entry, but `a` is the one that cannot go.
+        assert_eq!(keys(&cache), ["a"]);
+
+        cache.insert("c".to_string(), package(), ArchiveChecks::default());
+        cache.clear();
+
+        assert_eq!(keys(&cache), ["a"]);
+        assert_eq!(cache.stats().pinned_entries, 1);
+        assert_eq!(cache.stats().bytes, estimate_size(&package()));
+    }
+
+    #[test]
+    fn pins_are_counted() {
+        let mut cache = cache_for(1);
+        cache.pin("a");
+        cache.pin("a");
+        cache.insert("a".to_string(), package(), ArchiveChecks::default());
+
+        cache.unpin("a");
+        cache.insert("b".to_string(), package(), ArchiveChecks::default());
+
+        assert_eq!(keys(&cache), ["a"]);
+    }
+
+    #[test]
+    fn unpinning_makes_an_entry_evictable_again() {
+        let mut cache = cache_for(1);
+        cache.pin("a");
+        cache.pin("b");
+        cache.insert("a".to_string(), package(), ArchiveChecks::default());
+        cache.insert("b".to_string(), package(), ArchiveChecks::default());
+        cache.insert("c".to_string(), package(), ArchiveChecks::default());
+        assert_eq!(keys(&cache), ["a", "b"]);
+
+        // Over budget once nothing holds `a`, so it goes straight away.
+        cache.unpin("a");
+        assert_eq!(keys(&cache), ["b"]);
+
+        cache.unpin("b");
+        cache.insert("d".to_string(), package(), ArchiveChecks::default());
+        assert_eq!(keys(&cache), ["d"]);
+    }
+}
@@ -0,0 +1,130 @@
+use std::fmt;
+
+use serde::Serialize;
+use wasm_bindgen::prelude::*;
+
+/// Every failure the exports can report. It crosses the wasm boundary as
+/// `{ code, message, details }` so the UI can tell a missing version from a
+/// dropped connection and only retry what can succeed on a second attempt.
+#[derive(Debug)]
+pub enum DiffError {
+    /// The registry answered 404 or 410, or its metadata does not list the
+    /// requested version.
+    NotFound(String),
+    /// The request never got a response: offline, DNS, CORS or an aborted
+    /// fetch.
+    Network(String),
+    /// Any other non-2xx response.
+    HttpStatus { status: u16, url: String },
+    /// The download is not an archive format diffpack can read.
+    UnsupportedFormat(String),
+    /// An archive or metadata document that could not be parsed, or whose
+    /// checksum did not match.
+    Corrupt(String),
+    /// Extraction stopped at a configured size or count limit.
+    LimitExceeded(String),
+    /// The version exists but publishes nothing diffpack can download.
+    NoArtifact(String),
+    /// A caller mistake: unknown registry or session, malformed coordinates or
+    /// configuration.
+    InvalidArgument(String),
+}
+
+impl DiffError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            DiffError::NotFound(_) => "not_found",
+            DiffError::Network(_) => "network",
+            DiffError::HttpStatus { .. } => "http_status",
+            DiffError::UnsupportedFormat(_) => "unsupported_format",
+            DiffError::Corrupt(_) => "corrupt",
+            DiffError::LimitExceeded(_) => "limit_exceeded",
+            DiffError::NoArtifact(_) => "no_artifact",
+            DiffError::InvalidArgument(_) => "invalid_argument",
+        }
+    }
+
+    /// Maps a failed response onto `NotFound` or `HttpStatus`.
+    pub fn from_status(status: u16, url: &str) -> Self {
+        match status {
+            404 | 410 => DiffError::NotFound(format!("Nothing found at {url}")),
+            status => DiffError::HttpStatus {
+                status,
+                url: url.to_string(),
+            },
+        }
+    }
+
+    fn details(&self) -> Option<ErrorDetails<'_>> {
+        match self {
+            DiffError::HttpStatus { status, url } => Some(ErrorDetails::HttpStatus {
+                status: *status,
+                url,
+            }),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for DiffError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DiffError::HttpStatus { status, url } => {
+                write!(f, "Failed to fetch {url} (HTTP {status})")
+            }
+            DiffError::NotFound(message)
+            | DiffError::Network(message)
+            | DiffError::UnsupportedFormat(message)
+            | DiffError::Corrupt(message)
+            | DiffError::LimitExceeded(message)
+            | DiffError::NoArtifact(message)
+            | DiffError::InvalidArgument(message) => f.write_str(message),
+        }
+    }
+}
+
+impl From<diffpack_core::Error> for DiffError {
+    fn from(err: diffpack_core::Error) -> Self {
+        match err {
+            diffpack_core::Error::UnsupportedFormat(_) => {
+                DiffError::UnsupportedFormat(err.to_string())
+            }
+            diffpack_core::Error::Corrupt(_) | diffpack_core::Error::MissingMember(_) => {
+                DiffError::Corrupt(err.to_string())
+            }
+            diffpack_core::Error::LimitExceeded(_) => DiffError::LimitExceeded(err.to_string()),
+        }
+    }
+}
+
+impl From<serde_wasm_bindgen::Error> for DiffError {
+    fn from(err: serde_wasm_bindgen::Error) -> Self {
+        DiffError::InvalidArgument(err.to_string())
+    }
+}
+
+#[derive(Serialize)]
+struct ErrorPayload<'a> {
+    code: &'static str,
+    message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    details: Option<ErrorDetails<'a>>,
+}
+
+#[derive(Serialize)]
+#[serde(untagged)]
+enum ErrorDetails<'a> {
+    HttpStatus { status: u16, url: &'a str },
+}
+
+impl From<DiffError> for JsValue {
+    fn from(err: DiffError) -> Self {
+        let payload = ErrorPayload {
+            code: err.code(),
+            message: err.to_string(),
+            details: err.details(),
+        };
+        serde_wasm_bindgen::to_value(&payload)
+            .unwrap_or_else(|_| JsValue::from_str(&payload.message))
+    }
+}
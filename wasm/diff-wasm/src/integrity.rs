@@ -0,0 +1,244 @@
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde::Serialize;
+use sha2::{Digest as _, Sha256, Sha512};
+
+use diffpack_core::dirhash;
+
+/// Whether a downloaded archive matched the checksum its registry publishes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Integrity {
+    Verified,
+    /// No checksum was published, it could not be fetched, or the archive came
+    /// from a mirror or private registry.
+    Unverified,
+    Mismatch,
+}
+
+/// A published checksum for an archive.
+pub enum Digest {
+    Sha256(Vec<u8>),
+    Sha512(Vec<u8>),
+    /// Go's `h1:` hash, taken over the module zip's entries rather than its
+    /// bytes.
+    GoModule(String),
+}
+
+impl Digest {
+    pub fn check(&self, bytes: &[u8]) -> Integrity {
+        let matches = match self {
+            Digest::Sha256(expected) => Sha256::digest(bytes).as_slice() == expected.as_slice(),
+            Digest::Sha512(expected) => Sha512::digest(bytes).as_slice() == expected.as_slice(),
+            Digest::GoModule(expected) => {
+                dirhash::hash_module_zip(bytes).is_ok_and(|actual| actual == *expected)
+            }
+        };
+        if matches {
+            Integrity::Verified
+        } else {
+            Integrity::Mismatch
+        }
+    }
+
+    pub fn sha256_hex(hex: &str) -> Option<Self> {
+        decode_hex(hex)
+            .filter(|bytes| bytes.len() == 32)
+            .map(Digest::Sha256)
+    }
+
+    /// Subresource Integrity metadata such as npm's `dist.integrity`. When
+    /// several hashes are listed the strongest supported one wins; `sha1`
+    /// alone is not trusted.
+    pub fn from_sri(sri: &str) -> Option<Self> {
+        let mut sha256 = None;
+        for token in sri.split_whitespace() {
+            let Some((algorithm, value)) = token.split_once('-') else {
+                continue;
+            };
+            let value = value.split('?').next().unwrap_or(value);
+            let Ok(bytes) = BASE64.decode(value) else {
+                continue;
+            };
+            match algorithm {
+                "sha512" if bytes.len() == 64 => return Some(Digest::Sha512(bytes)),
+                "sha256" if bytes.len() == 32 => sha256 = Some(Digest::Sha256(bytes)),
+                _ => {}
+            }
+        }
+        sha256
+    }
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::package::{verify_package_archive, PackageArchive};
+
+    const ARCHIVE: &[u8] = b"the archive as published";
+    const TAMPERED: &[u8] = b"the archive as served";
+    /// Stands in for a SHA-1, which is never computed since it is not trusted.
+    const SHA1: [u8; 20] = [0xab; 20];
+
+    fn sri(algorithm: &str, digest: &[u8]) -> String {
+        format!("{algorithm}-{}", BASE64.encode(digest))
+    }
+
+    fn hex(digest: &[u8]) -> String {
+        digest.iter().map(|byte| format!("{byte:02x}")).collect()
+    }
+
+    fn npm_document(integrity: &str) -> String {
+        format!(r#"{{"name":"demo","version":"1.0.0","dist":{{"integrity":"{integrity}"}}}}"#)
+    }
+
+    fn crates_index(cksum: &str) -> String {
+        format!(
+            "{{\"name\":\"demo\",\"vers\":\"0.9.0\",\"cksum\":\"{}\"}}\n\
+             {{\"name\":\"demo\",\"vers\":\"1.0.0\",\"cksum\":\"{cksum}\"}}\n",
+            "0".repeat(64)
+        )
+    }
+
+    fn hex_release(checksum: &str) -> String {
+        format!(r#"{{"version":"1.0.0","checksum":"{checksum}"}}"#)
+    }
+
+    fn pypi_metadata(sha256: &str) -> String {
+        format!(
+            r#"{{"urls":[
+                {{"packagetype":"bdist_wheel","url":"https://files.example/demo-1.0.0-py3-none-any.whl","digests":{{"sha256":"{}"}}}},
+                {{"packagetype":"sdist","url":"https://files.example/demo-1.0.0.tar.gz","digests":{{"sha256":"{sha256}"}}}}
+            ]}}"#,
+            "0".repeat(64)
+        )
+    }
+
+    /// How the published archive and a tampered copy verify against the given
+    /// registry documents.
+    fn verdicts(
+        registry: &str,
+        metadata: Option<String>,
+        integrity: Option<String>,
+    ) -> (Integrity, Integrity) {
+        let verify = |bytes: &[u8]| {
+            let archive = PackageArchive {
+                bytes: bytes.to_vec(),
+                metadata: metadata.clone().map(String::into_bytes),
+                integrity: integrity.clone().map(String::into_bytes),
+            };
+            verify_package_archive(registry, "demo", "1.0.0", &archive).unwrap()
+        };
+        (verify(ARCHIVE), verify(TAMPERED))
+    }
+
+    #[test]
+    fn checks_each_registry_against_its_published_digest() {
+        use Integrity::*;
+        let sha512 = Sha512::digest(ARCHIVE);
+        let sha256 = Sha256::digest(ARCHIVE);
+
+        let cases = [
+            (
+                "npm sha512",
+                verdicts("npm", None, Some(npm_document(&sri("sha512", &sha512)))),
+                (Verified, Mismatch),
+            ),
+            (
+                "npm sha256",
+                verdicts("npm", None, Some(npm_document(&sri("sha256", &sha256)))),
+                (Verified, Mismatch),
+            ),
+            (
+                "npm sha512 listed after sha1",
+                verdicts(
+                    "npm",
+                    None,
+                    Some(npm_document(&format!(
+                        "{} {}",
+                        sri("sha1", &SHA1),
+                        sri("sha512", &sha512)
+                    ))),
+                ),
+                (Verified, Mismatch),
+            ),
+            (
+                "npm sha1 only",
+                verdicts("npm", None, Some(npm_document(&sri("sha1", &SHA1)))),
+                (Unverified, Unverified),
+            ),
+            (
+                "npm without a version document",
+                verdicts("npm", None, None),
+                (Unverified, Unverified),
+            ),
+            (
+                "crates cksum",
+                verdicts("crates", None, Some(crates_index(&hex(&sha256)))),
+                (Verified, Mismatch),
+            ),
+            (
+                "crates version missing from the index",
+                verdicts(
+                    "crates",
+                    None,
+                    Some(crates_index(&hex(&sha256)).replace("1.0.0", "1.0.1")),
+                ),
+                (Unverified, Unverified),
+            ),
+            (
+                "hex outer checksum",
+                verdicts("hex", None, Some(hex_release(&hex(&sha256)))),
+                (Verified, Mismatch),
+            ),
+            (
+                "hex without a release document",
+                verdicts("hex", None, None),
+                (Unverified, Unverified),
+            ),
+            (
+                "pypi sdist sha256",
+                verdicts("pypi", Some(pypi_metadata(&hex(&sha256))), None),
+                (Verified, Mismatch),
+            ),
+            (
+                "pypi sdist sha256 in upper case",
+                verdicts(
+                    "pypi",
+                    Some(pypi_metadata(&hex(&sha256).to_uppercase())),
+                    None,
+                ),
+                (Verified, Mismatch),
+            ),
+        ];
+        for (case, actual, expected) in cases {
+            assert_eq!(actual, expected, "{case}");
+        }
+    }
+
+    #[test]
+    fn sri_prefers_the_strongest_supported_hash() {
+        let sha512 = Sha512::digest(ARCHIVE);
+        let sha256 = Sha256::digest(ARCHIVE);
+        let both = format!("{} {}", sri("sha256", &sha256), sri("sha512", &sha512));
+
+        assert!(matches!(Digest::from_sri(&both), Some(Digest::Sha512(_))));
+        assert!(matches!(
+            Digest::from_sri(&format!("{}?opt", sri("sha256", &sha256))),
+            Some(Digest::Sha256(_))
+        ));
+        assert!(Digest::from_sri(&sri("sha1", &SHA1)).is_none());
+        assert!(Digest::from_sri(&sri("sha512", &sha256)).is_none());
+        assert!(Digest::from_sri("sha512-not base64").is_none());
+    }
+}
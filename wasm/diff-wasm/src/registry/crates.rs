@@ -0,0 +1,73 @@
+use serde::Deserialize;
+
+use super::Registry;
+use crate::error::DiffError;
+use crate::integrity::Digest;
+
+pub struct Crates;
+
+/// The official sparse index. It is only asked about crates downloaded from
+/// the public registry; see [`Resolved::is_public`](super::Resolved::is_public).
+const INDEX_URL: &str = "https://index.crates.io";
+
+#[derive(Deserialize)]
+struct IndexEntry {
+    vers: String,
+    cksum: String,
+}
+
+impl Registry for Crates {
+    fn default_base_url(&self) -> &'static str {
+        "https://static.crates.io"
+    }
+
+    fn archive_url(
+        &self,
+        base: &str,
+        pkg: &str,
+        version: &str,
+        _metadata: Option<&[u8]>,
+    ) -> Result<String, DiffError> {
+        Ok(format!("{base}/crates/{pkg}/{pkg}-{version}.crate"))
+    }
+
+    fn integrity_url(&self, _base: &str, pkg: &str, _version: &str) -> Option<String> {
+        Some(format!("{INDEX_URL}/{}", index_path(pkg)?))
+    }
+
+    /// The index file has one JSON line per published version.
+    fn expected_digest(
+        &self,
+        _pkg: &str,
+        version: &str,
+        _metadata: Option<&[u8]>,
+        integrity: Option<&[u8]>,
+    ) -> Option<Digest> {
+        let index = std::str::from_utf8(integrity?).ok()?;
+        index
+            .lines()
+            .filter_map(|line| serde_json::from_str::<IndexEntry>(line).ok())
+            .find(|entry| entry.vers == version)
+            .and_then(|entry| Digest::sha256_hex(&entry.cksum))
+    }
+
+    fn is_manifest(&self, path: &str) -> bool {
+        path == "Cargo.toml"
+    }
+}
+
+/// `1/a`, `2/ab`, `3/a/abc`, and `ab/cd/abcd…` for longer names, lower-cased.
+/// Crate names are ASCII, so anything else has no index entry.
+fn index_path(pkg: &str) -> Option<String> {
+    if !pkg.is_ascii() {
+        return None;
+    }
+    let name = pkg.to_ascii_lowercase();
+    Some(match name.len() {
+        0 => return None,
+        1 => format!("1/{name}"),
+        2 => format!("2/{name}"),
+        3 => format!("3/{}/{name}", &name[..1]),
+        _ => format!("{}/{}/{name}", &name[..2], &name[2..4]),
+    })
+}
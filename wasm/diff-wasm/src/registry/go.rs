@@ -0,0 +1,209 @@
+use std::collections::HashMap;
+
+use super::{ArchiveSource, Fallback, Registry};
+use crate::error::DiffError;
+use crate::integrity::Digest;
+use crate::package::PackageArchive;
+use diffpack_core::archive::{ensure_directories, extract_archive_bytes_with, strip_common_root};
+use diffpack_core::dirhash::{self, GoSumLine};
+use diffpack_core::types::FileMapEntry;
+
+pub struct Go;
+
+/// The checksum database is queried directly rather than through the proxy,
+/// and only for modules from the public proxy. Its signed tree is not checked;
+/// the lookup is trusted as far as the TLS connection to it.
+const SUMDB_URL: &str = "https://sum.golang.org";
+
+impl Registry for Go {
+    fn default_base_url(&self) -> &'static str {
+        "https://proxy.golang.org"
+    }
+
+    /// URL on the first proxy of the list; `archive_sources` has the rest.
+    fn archive_url(
+        &self,
+        base: &str,
+        pkg: &str,
+        version: &str,
+        metadata: Option<&[u8]>,
+    ) -> Result<String, DiffError> {
+        let mut sources = self.archive_sources(base, pkg, version, metadata)?;
+        Ok(sources.remove(0).url)
+    }
+
+    /// `base` follows `GOPROXY` list semantics: after a proxy followed by `,`
+    /// the next one is only tried on 404 or 410, after one followed by `|` it
+    /// is tried on any error. `off` ends the list, and `direct` is skipped
+    /// because version control checkouts cannot be done from the browser.
+    fn archive_sources(
+        &self,
+        base: &str,
+        pkg: &str,
+        version: &str,
+        _metadata: Option<&[u8]>,
+    ) -> Result<Vec<ArchiveSource>, DiffError> {
+        let mut sources = Vec::new();
+        let mut rest = base;
+        while !rest.is_empty() {
+            let end = rest.find([',', '|']).unwrap_or(rest.len());
+            let proxy = rest[..end].trim().trim_end_matches('/');
+            let fallback = if rest[end..].starts_with('|') {
+                Fallback::AnyError
+            } else {
+                Fallback::NotFound
+            };
+            rest = rest.get(end + 1..).unwrap_or("");
+
+            match proxy {
+                "off" => break,
+                "" | "direct" => {}
+                proxy => sources.push(ArchiveSource {
+                    url: build_go_zip_url(proxy, pkg, version),
+                    fallback,
+                }),
+            }
+        }
+
+        if sources.is_empty() {
+            // The list itself is not echoed: entries may embed credentials.
+            return Err(DiffError::InvalidArgument(
+                "GOPROXY list has no usable proxy".to_string(),
+            ));
+        }
+        Ok(sources)
+    }
+
+    fn integrity_url(&self, _base: &str, pkg: &str, version: &str) -> Option<String> {
+        Some(format!(
+            "{SUMDB_URL}/lookup/{}@{}",
+            escape_go_module_path(pkg),
+            escape_go_module_path(version)
+        ))
+    }
+
+    /// A lookup answers with `<module> <version> h1:…` for the zip and
+    /// `<module> <version>/go.mod h1:…` for the go.mod, followed by the signed
+    /// tree head. Private modules are not in the database and stay unverified.
+    fn expected_digest(
+        &self,
+        pkg: &str,
+        version: &str,
+        _metadata: Option<&[u8]>,
+        integrity: Option<&[u8]>,
+    ) -> Option<Digest> {
+        let lookup = std::str::from_utf8(integrity?).ok()?;
+        lookup
+            .lines()
+            .filter_map(GoSumLine::parse)
+            .find(|line| line.module == pkg && line.version == version && !line.go_mod)
+            .map(|line| Digest::GoModule(line.hash.to_string()))
+    }
+
+    /// The `h1:` hash `go.sum` pins the module zip to.
+    fn lockfile_hash(&self, archive: &PackageArchive) -> Option<String> {
+        dirhash::hash_module_zip(&archive.bytes).ok()
+    }
+
+    fn extract(
+        &self,
+        pkg: &str,
+        version: &str,
+        archive: &PackageArchive,
+    ) -> Result<HashMap<String, FileMapEntry>, DiffError> {
+        let files = extract_archive_bytes_with(&archive.bytes, false)?;
+        Ok(strip_go_module_root(files, pkg, version))
+    }
+
+    fn is_manifest(&self, path: &str) -> bool {
+        path == "go.mod"
+    }
+}
+
+/// The module proxy serves lower-cased paths, escaping each uppercase letter as
+/// `!` followed by its lowercase form, so `Masterminds` becomes `!masterminds`.
+/// Requesting the unescaped path is a 404.
+fn escape_go_module_path(pkg: &str) -> String {
+    let mut escaped = String::with_capacity(pkg.len());
+    for ch in pkg.chars() {
+        if ch.is_ascii_uppercase() {
+            escaped.push('!');
+            escaped.push(ch.to_ascii_lowercase());
+        } else {
+            escaped.push(ch);
+        }
+    }
+    escaped
+}
+
+fn build_go_zip_url(proxy: &str, pkg: &str, version: &str) -> String {
+    format!("{proxy}/{}/@v/{version}.zip", escape_go_module_path(pkg))
+}
+
+/// Module zips prefix every entry with `<module>@<version>/`. That prefix embeds
+/// the version, so the two sides of a diff would share no paths at all and every
+/// file would read as removed-then-added. Unlike the other registries the prefix
+/// spans several components (`github.com/sirupsen/logrus@v1.9.3/`), which is why
+/// `strip_common_root` cannot do the job. Entry names keep the module's real
+/// casing, so the unescaped path is the one to strip.
+fn strip_go_module_root(
+    files: HashMap<String, FileMapEntry>,
+    pkg: &str,
+    version: &str,
+) -> HashMap<String, FileMapEntry> {
+    let prefix = format!("{pkg}@{version}/");
+    if !files.keys().any(|path| path.starts_with(&prefix)) {
+        return strip_common_root(files);
+    }
+
+    let mut stripped = HashMap::new();
+    for (path, entry) in files {
+        if let Some(rest) = path.strip_prefix(&prefix) {
+            if !rest.is_empty() {
+                stripped.insert(rest.to_string(), entry);
+            }
+        }
+    }
+
+    ensure_directories(&mut stripped);
+    stripped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A checksum database lookup, signed tree head included.
+    const LOOKUP: &str = "\
+123456
+github.com/davecgh/go-spew v1.1.0 h1:ZDRjVQ15GmhC3fiQ8ni8+OwkZQO4DARzQgrnXU1Liz8=
+github.com/davecgh/go-spew v1.1.0/go.mod h1:J7Y8YcW2NihsgmVo/mv3lAwl/skON4iLHjSsI+c5H38=
+
+go.sum database tree
+31415926
+abcdef=
+
+— sum.golang.org Az3grnmrIq==
+";
+
+    fn expected(pkg: &str, version: &str) -> Option<String> {
+        match Go.expected_digest(pkg, version, None, Some(LOOKUP.as_bytes())) {
+            Some(Digest::GoModule(hash)) => Some(hash),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn takes_the_zip_hash_rather_than_the_go_mod_hash() {
+        assert_eq!(
+            expected("github.com/davecgh/go-spew", "v1.1.0").as_deref(),
+            Some("h1:ZDRjVQ15GmhC3fiQ8ni8+OwkZQO4DARzQgrnXU1Liz8=")
+        );
+    }
+
+    #[test]
+    fn ignores_lines_for_other_modules_and_versions() {
+        assert_eq!(expected("github.com/davecgh/go-spew", "v1.1.1"), None);
+        assert_eq!(expected("github.com/pmezard/go-difflib", "v1.0.0"), None);
+    }
+}
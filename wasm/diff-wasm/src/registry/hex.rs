@@ -0,0 +1,123 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+
+use super::Registry;
+use crate::error::DiffError;
+use crate::integrity::Digest;
+use crate::package::PackageArchive;
+use diffpack_core::archive::{archive_member, insert_synthetic, Extraction};
+use diffpack_core::types::FileMapEntry;
+
+/// Where `metadata.config` is surfaced. Like the gemspec it sits outside the
+/// inner archive, so it gets a path the package's own files cannot use.
+const METADATA_PATH: &str = ".hex/metadata.config";
+
+pub struct Hex;
+
+/// The hex.pm API. It is only asked about packages downloaded from the public
+/// repository; see [`Resolved::is_public`](super::Resolved::is_public).
+const API_URL: &str = "https://hex.pm/api";
+
+#[derive(Deserialize)]
+struct Release {
+    checksum: String,
+}
+
+impl Registry for Hex {
+    fn default_base_url(&self) -> &'static str {
+        "https://repo.hex.pm"
+    }
+
+    fn archive_url(
+        &self,
+        base: &str,
+        pkg: &str,
+        version: &str,
+        _metadata: Option<&[u8]>,
+    ) -> Result<String, DiffError> {
+        Ok(format!("{base}/tarballs/{pkg}-{version}.tar"))
+    }
+
+    fn integrity_url(&self, _base: &str, pkg: &str, version: &str) -> Option<String> {
+        Some(format!("{API_URL}/packages/{pkg}/releases/{version}"))
+    }
+
+    /// The release's `checksum` is the outer checksum, a SHA-256 of the whole
+    /// tarball. The `CHECKSUM` member inside the tarball is not used, since
+    /// it travels with the bytes it claims to cover.
+    fn expected_digest(
+        &self,
+        _pkg: &str,
+        _version: &str,
+        _metadata: Option<&[u8]>,
+        integrity: Option<&[u8]>,
+    ) -> Option<Digest> {
+        let release: Release = serde_json::from_slice(integrity?).ok()?;
+        Digest::sha256_hex(&release.checksum)
+    }
+
+    /// A Hex tarball is an uncompressed tar of `VERSION`, `metadata.config`,
+    /// `contents.tar.gz` and `CHECKSUM`. The inner archive has no root
+    /// directory, and spends the tarball's limits.
+    fn extract(
+        &self,
+        _pkg: &str,
+        _version: &str,
+        archive: &PackageArchive,
+    ) -> Result<HashMap<String, FileMapEntry>, DiffError> {
+        let mut extraction = Extraction::new(&archive.bytes)?;
+        let outer = extraction.extract(&archive.bytes, false)?;
+
+        let contents = archive_member(&outer, "contents.tar.gz")?;
+        let mut files = extraction.extract(contents, false)?;
+
+        let metadata = archive_member(&outer, "metadata.config")?;
+        insert_synthetic(&mut files, METADATA_PATH, FileMapEntry::file(metadata.to_vec()));
+
+        Ok(files)
+    }
+
+    fn is_manifest(&self, path: &str) -> bool {
+        path == METADATA_PATH
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use diffpack_core::archive::test_support::{gzip, tar_of};
+    use diffpack_core::types::AnomalyKind;
+
+    fn tarball(contents: &[(&str, &[u8])]) -> PackageArchive {
+        let contents = gzip(&tar_of(contents));
+        PackageArchive {
+            bytes: tar_of(&[
+                ("VERSION", b"3"),
+                ("metadata.config", b"{<<\"name\">>,<<\"demo\">>}.\n"),
+                ("contents.tar.gz", &contents),
+            ]),
+            metadata: None,
+            integrity: None,
+        }
+    }
+
+    #[test]
+    fn flags_a_package_file_at_the_metadata_path() {
+        let archive = tarball(&[
+            ("lib/demo.ex", b"defmodule Demo do\nend\n"),
+            (METADATA_PATH, b"{<<\"name\">>,<<\"other\">>}.\n"),
+        ]);
+
+        let files = Hex.extract("demo", "1.0.0", &archive).unwrap();
+
+        assert_eq!(files[METADATA_PATH].bytes, b"{<<\"name\">>,<<\"demo\">>}.\n");
+        let kinds: Vec<_> = files[METADATA_PATH]
+            .anomalies
+            .iter()
+            .map(|anomaly| anomaly.kind)
+            .collect();
+        assert_eq!(kinds, [AnomalyKind::Duplicate]);
+        assert!(files["lib/demo.ex"].anomalies.is_empty());
+    }
+}
@@ -0,0 +1,181 @@
+use std::collections::HashMap;
+
+use super::{ArchiveSource, Registry};
+use crate::error::DiffError;
+use crate::package::PackageArchive;
+use diffpack_core::archive::{extract_archive_bytes_with, insert_synthetic};
+use diffpack_core::classfile::summarize_class;
+use diffpack_core::types::{ContentKind, FileMapEntry, FileType};
+
+/// Where the pom is placed in the extracted tree. Jars only carry it under
+/// `META-INF/maven/`, and only when the build plugin chose to include it.
+const POM_PATH: &str = ".maven/pom.xml";
+
+pub struct Maven;
+
+impl Registry for Maven {
+    fn default_base_url(&self) -> &'static str {
+        "https://repo1.maven.org/maven2"
+    }
+
+    /// The pom doubles as the metadata document so it can be shown next to the
+    /// jar contents.
+    fn metadata_url(&self, base: &str, pkg: &str, version: &str) -> Option<String> {
+        let artifact = artifact_url(base, pkg, version).ok()?;
+        Some(format!("{artifact}.pom"))
+    }
+
+    fn archive_url(
+        &self,
+        base: &str,
+        pkg: &str,
+        version: &str,
+        _metadata: Option<&[u8]>,
+    ) -> Result<String, DiffError> {
+        Ok(format!("{}-sources.jar", artifact_url(base, pkg, version)?))
+    }
+
+    /// Not every artifact publishes sources, so the compiled jar is the
+    /// fallback when the sources jar is missing.
+    fn archive_sources(
+        &self,
+        base: &str,
+        pkg: &str,
+        version: &str,
+        _metadata: Option<&[u8]>,
+    ) -> Result<Vec<ArchiveSource>, DiffError> {
+        let artifact = artifact_url(base, pkg, version)?;
+        Ok(vec![
+            ArchiveSource::new(format!("{artifact}-sources.jar")),
+            ArchiveSource::new(format!("{artifact}.jar")),
+        ])
+    }
+
+    /// Jars have no root directory. Class files have their content replaced by
+    /// a declaration summary, keeping the anomalies and mode extraction
+    /// recorded for them; any that fail to parse are left as binaries.
+    fn extract(
+        &self,
+        _pkg: &str,
+        _version: &str,
+        archive: &PackageArchive,
+    ) -> Result<HashMap<String, FileMapEntry>, DiffError> {
+        let mut files = extract_archive_bytes_with(&archive.bytes, false)?;
+        for (path, entry) in files.iter_mut() {
+            if !matches!(entry.file_type, FileType::File) || !path.ends_with(".class") {
+                continue;
+            }
+            if let Ok(summary) = summarize_class(&entry.bytes) {
+                entry.bytes = summary.into_bytes();
+                entry.kind = ContentKind::detect(&entry.bytes);
+            }
+        }
+
+        if let Some(pom) = &archive.metadata {
+            insert_synthetic(&mut files, POM_PATH, FileMapEntry::file(pom.clone()));
+        }
+        Ok(files)
+    }
+
+    fn is_manifest(&self, path: &str) -> bool {
+        path == POM_PATH
+    }
+}
+
+/// `groupId:artifactId` resolves to
+/// `<base>/<group/as/path>/<artifactId>/<version>/<artifactId>-<version>`.
+fn artifact_url(base: &str, pkg: &str, version: &str) -> Result<String, DiffError> {
+    let (group, artifact) = pkg
+        .split_once(':')
+        .filter(|(group, artifact)| !group.is_empty() && !artifact.is_empty())
+        .ok_or_else(|| {
+            DiffError::InvalidArgument(format!(
+                "Maven packages must be given as groupId:artifactId, got {pkg}"
+            ))
+        })?;
+    let group_path = group.replace('.', "/");
+    Ok(format!(
+        "{base}/{group_path}/{artifact}/{version}/{artifact}-{version}"
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use diffpack_core::archive::test_support::zip_with_modes;
+    use diffpack_core::types::AnomalyKind;
+
+    /// A jar of `(path, contents, unix mode)` entries.
+    fn jar(files: &[(&str, &[u8], u32)], pom: Option<&[u8]>) -> PackageArchive {
+        PackageArchive {
+            bytes: zip_with_modes(files),
+            metadata: pom.map(<[u8]>::to_vec),
+            integrity: None,
+        }
+    }
+
+    /// `public class Demo` with no members.
+    fn demo_class() -> Vec<u8> {
+        let mut class = vec![0xca, 0xfe, 0xba, 0xbe, 0, 0, 0, 52, 0, 5];
+        for name in ["Demo", "java/lang/Object"] {
+            class.push(1);
+            class.extend((name.len() as u16).to_be_bytes());
+            class.extend(name.as_bytes());
+        }
+        class.extend([7, 0, 1, 7, 0, 2]);
+        // Access flags, this and super class, then no interfaces, fields,
+        // methods or attributes.
+        class.extend([0, 0x21, 0, 3, 0, 4, 0, 0, 0, 0, 0, 0, 0, 0]);
+        class
+    }
+
+    fn anomaly_kinds(entry: &FileMapEntry) -> Vec<AnomalyKind> {
+        entry.anomalies.iter().map(|anomaly| anomaly.kind).collect()
+    }
+
+    #[test]
+    fn flags_a_jar_entry_at_the_pom_path() {
+        let archive = jar(
+            &[
+                ("Demo.java", b"class Demo {}\n", 0o644),
+                (POM_PATH, b"<project/>", 0o644),
+            ],
+            Some(b"<project><artifactId>demo</artifactId></project>"),
+        );
+
+        let files = Maven.extract("org.example:demo", "1.0.0", &archive).unwrap();
+
+        assert_eq!(
+            files[POM_PATH].bytes,
+            b"<project><artifactId>demo</artifactId></project>"
+        );
+        assert_eq!(anomaly_kinds(&files[POM_PATH]), [AnomalyKind::Duplicate]);
+        assert!(anomaly_kinds(&files["Demo.java"]).is_empty());
+        assert!(matches!(files[".maven"].file_type, FileType::Directory));
+    }
+
+    #[test]
+    fn summarized_classes_keep_their_anomalies() {
+        let class = demo_class();
+        // The second name resolves to the first, as zips cannot list a name
+        // twice.
+        let archive = jar(
+            &[
+                ("com/example/Demo.class", b"\xca\xfe", 0o644),
+                ("com/example/./Demo.class", &class, 0o750),
+                ("com/example/Notes.class", b"not a class", 0o644),
+            ],
+            None,
+        );
+
+        let files = Maven.extract("org.example:demo", "1.0.0", &archive).unwrap();
+
+        let entry = &files["com/example/Demo.class"];
+        assert_eq!(entry.kind, ContentKind::Text);
+        assert!(entry.text().unwrap().contains("class Demo"), "{:?}", entry.text());
+        assert_eq!(anomaly_kinds(entry), [AnomalyKind::Duplicate]);
+        assert_eq!(entry.mode, Some(0o750));
+        assert_eq!(files["com/example/Notes.class"].bytes, b"not a class");
+        assert!(matches!(files["com/example"].file_type, FileType::Directory));
+    }
+}
@@ -0,0 +1,411 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde::Deserialize;
+
+use crate::error::DiffError;
+use crate::integrity::Digest;
+use crate::package::PackageArchive;
+use diffpack_core::archive::extract_archive_bytes;
+use diffpack_core::types::{DiffFileEntry, FileMapEntry, FileType};
+
+mod crates;
+mod go;
+mod hex;
+mod maven;
+mod npm;
+mod nuget;
+mod packagist;
+mod pub_dev;
+mod pypi;
+mod rubygems;
+
+/// Per-registry settings supplied from JavaScript through `set_registry_config`.
+#[derive(Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RegistryConfig {
+    /// Replaces the public registry's base URL, e.g. an Artifactory remote.
+    /// For `go` this is a `GOPROXY` list.
+    pub base_url: Option<String>,
+    /// Sent with every request to the base URL's hosts.
+    pub auth: Option<Credentials>,
+    /// npm-style scope mapping, keyed by scope (`@corp`). Packages in a listed
+    /// scope use its registry and token instead of `base_url` and `auth`; a
+    /// scope with its own registry never receives `auth`.
+    #[serde(default)]
+    pub scopes: HashMap<String, ScopeConfig>,
+}
+
+/// The equivalent of `.npmrc`'s `@scope:registry` and `//host/:_authToken`.
+#[derive(Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScopeConfig {
+    pub base_url: Option<String>,
+    pub auth_token: Option<String>,
+}
+
+/// Deliberately not `Debug`, so credentials cannot end up in formatted errors.
+#[derive(Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum Credentials {
+    Bearer {
+        token: String,
+    },
+    Basic {
+        username: String,
+        password: String,
+    },
+    /// Sent verbatim as the `Authorization` header, as Cargo does for
+    /// alternate registries.
+    Token {
+        token: String,
+    },
+}
+
+impl Credentials {
+    pub fn authorization(&self) -> String {
+        match self {
+            Credentials::Bearer { token } => format!("Bearer {token}"),
+            Credentials::Basic { username, password } => {
+                format!("Basic {}", BASE64.encode(format!("{username}:{password}")))
+            }
+            Credentials::Token { token } => token.clone(),
+        }
+    }
+}
+
+/// A registry together with where, and as whom, to reach it for one package.
+pub struct Resolved {
+    pub registry: &'static dyn Registry,
+    /// Base URL without a trailing slash.
+    pub base: String,
+    credentials: Option<Credentials>,
+}
+
+impl Resolved {
+    /// Credentials to send to `url`. Metadata documents often point at other
+    /// hosts (PyPI's file CDN, GitHub for Packagist dists), and those must
+    /// never see the registry's token.
+    pub fn credentials_for(&self, url: &str) -> Option<&Credentials> {
+        let credentials = self.credentials.as_ref()?;
+        let origin = origin(url)?;
+        self.base
+            .split([',', '|'])
+            .any(|base| origin_matches(base.trim(), origin))
+            .then_some(credentials)
+    }
+
+    /// Whether every host in `base` is the registry's public default.
+    /// Checksums are only looked up for public registries: a mirror cannot
+    /// vouch for its own downloads, and asking the public checksum source
+    /// about a private package would leak its name.
+    pub fn is_public(&self) -> bool {
+        let default = self.registry.default_base_url();
+        self.base
+            .split([',', '|'])
+            .map(|base| base.trim().trim_end_matches('/'))
+            .filter(|base| !matches!(*base, "" | "direct" | "off"))
+            .all(|base| base.eq_ignore_ascii_case(default))
+    }
+}
+
+fn origin(url: &str) -> Option<&str> {
+    let scheme_end = url.find("://")? + 3;
+    let host_end = url[scheme_end..]
+        .find(['/', '?', '#'])
+        .map_or(url.len(), |end| scheme_end + end);
+    Some(&url[..host_end])
+}
+
+fn origin_matches(base: &str, origin: &str) -> bool {
+    self::origin(base).is_some_and(|base| base.eq_ignore_ascii_case(origin))
+}
+
+thread_local! {
+    static REGISTRY_CONFIGS: RefCell<HashMap<String, RegistryConfig>> = RefCell::new(HashMap::new());
+}
+
+/// When `fetch_package_archive` may move on from a failed archive source to
+/// the next one.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Fallback {
+    /// Only when the source answered 404 or 410.
+    NotFound,
+    /// On any failure, including network errors and other statuses.
+    AnyError,
+}
+
+pub struct ArchiveSource {
+    pub url: String,
+    pub fallback: Fallback,
+}
+
+impl ArchiveSource {
+    pub fn new(url: String) -> Self {
+        Self {
+            url,
+            fallback: Fallback::NotFound,
+        }
+    }
+}
+
+/// One package ecosystem. `fetch_package_archive` drives these steps in order:
+/// fetch the optional metadata document, pick the archive to download from it,
+/// check it against the published checksum, then hand the downloaded bytes back
+/// for extraction. `base` is the registry
+/// root from `base_url`, without a trailing slash.
+pub trait Registry {
+    /// Public root that URLs are built from unless a mirror is configured.
+    fn default_base_url(&self) -> &'static str;
+
+    /// Document that has to be read before the archive URL is known, such as
+    /// PyPI's JSON API. Registries with predictable archive URLs return `None`.
+    fn metadata_url(&self, _base: &str, _pkg: &str, _version: &str) -> Option<String> {
+        None
+    }
+
+    /// URL of the archive to diff. `metadata` holds the body fetched from
+    /// `metadata_url`, if there was one.
+    fn archive_url(
+        &self,
+        base: &str,
+        pkg: &str,
+        version: &str,
+        metadata: Option<&[u8]>,
+    ) -> Result<String, DiffError>;
+
+    /// Every source worth trying, in order, for registries that fall back to a
+    /// second artifact or a second mirror when the preferred one is missing.
+    fn archive_sources(
+        &self,
+        base: &str,
+        pkg: &str,
+        version: &str,
+        metadata: Option<&[u8]>,
+    ) -> Result<Vec<ArchiveSource>, DiffError> {
+        Ok(vec![ArchiveSource::new(
+            self.archive_url(base, pkg, version, metadata)?,
+        )])
+    }
+
+    /// Document with the archive's published checksum, for registries that keep
+    /// it outside the metadata document. It is fetched on its own, and failing
+    /// to fetch it leaves the archive unverified rather than failing the diff.
+    fn integrity_url(&self, _base: &str, _pkg: &str, _version: &str) -> Option<String> {
+        None
+    }
+
+    /// Checksum the registry publishes for the archive, read from `metadata` or
+    /// from the body fetched from `integrity_url`.
+    fn expected_digest(
+        &self,
+        _pkg: &str,
+        _version: &str,
+        _metadata: Option<&[u8]>,
+        _integrity: Option<&[u8]>,
+    ) -> Option<Digest> {
+        None
+    }
+
+    /// The archive's hash in the form the ecosystem's lockfile records it, so a
+    /// build's pinned hash can be compared with the code that was reviewed.
+    fn lockfile_hash(&self, _archive: &PackageArchive) -> Option<String> {
+        None
+    }
+
+    /// Turns the archive into a file map, stripping whatever root directory the
+    /// ecosystem wraps packages in so both versions share paths.
+    fn extract(
+        &self,
+        _pkg: &str,
+        _version: &str,
+        archive: &PackageArchive,
+    ) -> Result<HashMap<String, FileMapEntry>, DiffError> {
+        Ok(extract_archive_bytes(&archive.bytes)?)
+    }
+
+    /// Whether `path` (after extraction) is the file that declares the package's
+    /// name, version and dependencies. Manifests are flagged in the diff tree so
+    /// dependency changes are easy to find.
+    fn is_manifest(&self, _path: &str) -> bool {
+        false
+    }
+}
+
+pub fn mark_manifests(node: &mut DiffFileEntry, registry: &dyn Registry) {
+    match node.file_type {
+        FileType::File => node.manifest = registry.is_manifest(&node.path),
+        FileType::Symlink => {}
+        FileType::Directory => {
+            for child in node.children.iter_mut().flatten() {
+                mark_manifests(child, registry);
+            }
+        }
+    }
+}
+
+pub fn lookup(registry: &str) -> Result<&'static dyn Registry, DiffError> {
+    match registry {
+        "npm" => Ok(&npm::Npm),
+        "crates" => Ok(&crates::Crates),
+        "pypi" => Ok(&pypi::PyPi),
+        "go" => Ok(&go::Go),
+        "rubygems" => Ok(&rubygems::RubyGems),
+        "nuget" => Ok(&nuget::NuGet),
+        "maven" => Ok(&maven::Maven),
+        "hex" => Ok(&hex::Hex),
+        "packagist" => Ok(&packagist::Packagist),
+        "pub" => Ok(&pub_dev::PubDev),
+        _ => Err(DiffError::InvalidArgument(format!(
+            "Unsupported registry: {registry}"
+        ))),
+    }
+}
+
+/// Looks up `registry` together with the base URL and credentials requests
+/// for `pkg` should use.
+pub fn resolve(registry: &str, pkg: &str) -> Result<Resolved, DiffError> {
+    let implementation = lookup(registry)?;
+    let config = config(registry);
+
+    let scope = pkg
+        .split_once('/')
+        .map(|(scope, _)| scope)
+        .filter(|scope| scope.starts_with('@'))
+        .and_then(|scope| config.scopes.get(scope));
+    // The registry-wide credentials belong to `base_url`'s hosts. A scope with
+    // a registry of its own gets its own token or none at all.
+    let (base_url, credentials) = match scope {
+        Some(scope) => {
+            let token = scope
+                .auth_token
+                .clone()
+                .map(|token| Credentials::Bearer { token });
+            match scope
+                .base_url
+                .clone()
+                .filter(|base| !base.trim().is_empty())
+            {
+                Some(base_url) => (Some(base_url), token),
+                None => (config.base_url, token.or(config.auth)),
+            }
+        }
+        None => (config.base_url, config.auth),
+    };
+
+    let base = base_url
+        .filter(|base| !base.trim().is_empty())
+        .unwrap_or_else(|| implementation.default_base_url().to_string());
+    Ok(Resolved {
+        registry: implementation,
+        base: base.trim().trim_end_matches('/').to_string(),
+        credentials,
+    })
+}
+
+pub fn config(registry: &str) -> RegistryConfig {
+    REGISTRY_CONFIGS.with(|configs| configs.borrow().get(registry).cloned().unwrap_or_default())
+}
+
+pub fn set_config(registry: &str, config: Option<RegistryConfig>) -> Result<(), DiffError> {
+    lookup(registry)?;
+    REGISTRY_CONFIGS.with(|configs| {
+        let mut configs = configs.borrow_mut();
+        match config {
+            Some(config) => configs.insert(registry.to_string(), config),
+            None => configs.remove(registry),
+        };
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scoped_config(scope: ScopeConfig) -> RegistryConfig {
+        RegistryConfig {
+            base_url: Some("https://npm.corp.example".to_string()),
+            auth: Some(Credentials::Bearer {
+                token: "registry-token".to_string(),
+            }),
+            scopes: HashMap::from([("@corp".to_string(), scope)]),
+        }
+    }
+
+    fn authorization(registry: &str, pkg: &str) -> Option<String> {
+        let resolved = resolve(registry, pkg).unwrap();
+        let url = format!("{}/{pkg}", resolved.base);
+        resolved
+            .credentials_for(&url)
+            .map(Credentials::authorization)
+    }
+
+    fn is_public(registry: &str, base_url: &str) -> bool {
+        let config = RegistryConfig {
+            base_url: Some(base_url.to_string()),
+            ..Default::default()
+        };
+        set_config(registry, Some(config)).unwrap();
+        resolve(registry, "pkg").unwrap().is_public()
+    }
+
+    #[test]
+    fn only_the_default_registry_is_public() {
+        assert!(resolve("npm", "pkg").unwrap().is_public());
+        assert!(is_public("npm", "https://registry.npmjs.org/"));
+        assert!(!is_public("npm", "https://npm.corp.example"));
+        assert!(!is_public("pypi", "https://pypi.corp.example"));
+        assert!(is_public("go", "https://proxy.golang.org,direct"));
+        assert!(!is_public(
+            "go",
+            "https://goproxy.corp.example,https://proxy.golang.org"
+        ));
+    }
+
+    #[test]
+    fn scope_with_its_own_registry_does_not_inherit_auth() {
+        let scope = ScopeConfig {
+            base_url: Some("https://scoped.example/".to_string()),
+            auth_token: None,
+        };
+        set_config("npm", Some(scoped_config(scope))).unwrap();
+
+        let resolved = resolve("npm", "@corp/pkg").unwrap();
+        assert_eq!(resolved.base, "https://scoped.example");
+        assert!(resolved.credentials.is_none());
+        assert_eq!(authorization("npm", "@corp/pkg"), None);
+    }
+
+    #[test]
+    fn scope_with_its_own_registry_uses_its_token() {
+        let scope = ScopeConfig {
+            base_url: Some("https://scoped.example".to_string()),
+            auth_token: Some("scope-token".to_string()),
+        };
+        set_config("npm", Some(scoped_config(scope))).unwrap();
+
+        assert_eq!(
+            authorization("npm", "@corp/pkg").as_deref(),
+            Some("Bearer scope-token")
+        );
+    }
+
+    #[test]
+    fn scope_without_a_registry_inherits_base_url_and_auth() {
+        set_config("npm", Some(scoped_config(ScopeConfig::default()))).unwrap();
+
+        let resolved = resolve("npm", "@corp/pkg").unwrap();
+        assert_eq!(resolved.base, "https://npm.corp.example");
+        assert_eq!(
+            authorization("npm", "@corp/pkg").as_deref(),
+            Some("Bearer registry-token")
+        );
+        assert_eq!(
+            authorization("npm", "@other/pkg").as_deref(),
+            Some("Bearer registry-token")
+        );
+    }
+}
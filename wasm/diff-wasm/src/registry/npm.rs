@@ -0,0 +1,56 @@
+use serde::Deserialize;
+
+use super::Registry;
+use crate::error::DiffError;
+use crate::integrity::Digest;
+
+pub struct Npm;
+
+#[derive(Deserialize)]
+struct NpmVersion {
+    dist: NpmDist,
+}
+
+#[derive(Deserialize)]
+struct NpmDist {
+    integrity: Option<String>,
+}
+
+impl Registry for Npm {
+    fn default_base_url(&self) -> &'static str {
+        "https://registry.npmjs.org"
+    }
+
+    fn archive_url(
+        &self,
+        base: &str,
+        pkg: &str,
+        version: &str,
+        _metadata: Option<&[u8]>,
+    ) -> Result<String, DiffError> {
+        let unscoped = pkg.split('/').nth(1).unwrap_or(pkg);
+        Ok(format!("{base}/{pkg}/-/{unscoped}-{version}.tgz"))
+    }
+
+    /// The per-version document, which carries `dist.integrity`.
+    fn integrity_url(&self, base: &str, pkg: &str, version: &str) -> Option<String> {
+        Some(format!("{base}/{pkg}/{version}"))
+    }
+
+    /// Versions published before 2017 only have a SHA-1 `shasum` and stay
+    /// unverified.
+    fn expected_digest(
+        &self,
+        _pkg: &str,
+        _version: &str,
+        _metadata: Option<&[u8]>,
+        integrity: Option<&[u8]>,
+    ) -> Option<Digest> {
+        let document: NpmVersion = serde_json::from_slice(integrity?).ok()?;
+        Digest::from_sri(&document.dist.integrity?)
+    }
+
+    fn is_manifest(&self, path: &str) -> bool {
+        path == "package.json"
+    }
+}
@@ -0,0 +1,156 @@
+use std::collections::HashMap;
+
+use super::Registry;
+use crate::error::DiffError;
+use crate::package::PackageArchive;
+use diffpack_core::archive::extract_archive_bytes_with;
+use diffpack_core::types::{FileMapEntry, FileType};
+
+pub struct NuGet;
+
+impl Registry for NuGet {
+    fn default_base_url(&self) -> &'static str {
+        "https://api.nuget.org/v3-flatcontainer"
+    }
+
+    /// The flat-container API only serves lower-cased IDs and versions.
+    fn archive_url(
+        &self,
+        base: &str,
+        pkg: &str,
+        version: &str,
+        _metadata: Option<&[u8]>,
+    ) -> Result<String, DiffError> {
+        let id = pkg.to_lowercase();
+        let version = version.to_lowercase();
+        Ok(format!("{base}/{id}/{version}/{id}.{version}.nupkg"))
+    }
+
+    /// A `.nupkg` is a zip with no root directory, so nothing is stripped.
+    fn extract(
+        &self,
+        _pkg: &str,
+        _version: &str,
+        archive: &PackageArchive,
+    ) -> Result<HashMap<String, FileMapEntry>, DiffError> {
+        let mut files = extract_archive_bytes_with(&archive.bytes, false)?;
+        let noise: Vec<String> = files
+            .iter()
+            .filter(|(path, entry)| {
+                matches!(entry.file_type, FileType::File) && is_packaging_noise(path)
+            })
+            .map(|(path, _)| path.clone())
+            .collect();
+        for path in noise {
+            files.remove(&path);
+            remove_empty_parents(&mut files, &path);
+        }
+        Ok(files)
+    }
+
+    fn is_manifest(&self, path: &str) -> bool {
+        !path.contains('/') && path.to_ascii_lowercase().ends_with(".nuspec")
+    }
+}
+
+/// OPC bookkeeping that `nuget pack` writes into every package. It carries
+/// per-build IDs, so it would differ on every release. The repository
+/// signature in `.signature.p7s` also changes each release but is kept, since
+/// it is the one file here a reviewer may want to see replaced.
+fn is_packaging_noise(path: &str) -> bool {
+    path == "[Content_Types].xml"
+        || path == "_rels/.rels"
+        || path
+            .strip_prefix("package/services/metadata/core-properties/")
+            .is_some_and(|name| !name.contains('/') && name.ends_with(".psmdcp"))
+}
+
+/// Drops the directories above a removed file that held nothing else, so
+/// `_rels/` does not linger as an empty folder.
+fn remove_empty_parents(files: &mut HashMap<String, FileMapEntry>, path: &str) {
+    let mut dir = path;
+    while let Some((parent, _)) = dir.rsplit_once('/') {
+        let prefix = format!("{parent}/");
+        if files.keys().any(|other| other.starts_with(&prefix)) {
+            return;
+        }
+        files.remove(parent);
+        dir = parent;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use diffpack_core::archive::test_support::{sorted_paths, zip_of};
+
+    fn nupkg(paths: &[&str]) -> PackageArchive {
+        let files: Vec<_> = paths.iter().map(|path| (*path, path.as_bytes())).collect();
+        PackageArchive {
+            bytes: zip_of(&files),
+            metadata: None,
+            integrity: None,
+        }
+    }
+
+    #[test]
+    fn only_opc_bookkeeping_is_noise() {
+        for path in [
+            "[Content_Types].xml",
+            "_rels/.rels",
+            "package/services/metadata/core-properties/0a1b2c3d.psmdcp",
+        ] {
+            assert!(is_packaging_noise(path), "{path}");
+        }
+        for path in [
+            ".signature.p7s",
+            "Demo.nuspec",
+            "_rels/other.rels",
+            "package/readme.md",
+            "package/services/metadata/core-properties/nested/0a1b2c3d.psmdcp",
+            "lib/_rels/.rels",
+            "lib/[Content_Types].xml",
+        ] {
+            assert!(!is_packaging_noise(path), "{path}");
+        }
+    }
+
+    #[test]
+    fn extracts_the_package_without_its_bookkeeping() {
+        let archive = nupkg(&[
+            "[Content_Types].xml",
+            "_rels/.rels",
+            "package/services/metadata/core-properties/0a1b2c3d.psmdcp",
+            "package/readme.md",
+            ".signature.p7s",
+            "Demo.nuspec",
+            "lib/net8.0/Demo.dll",
+        ]);
+
+        let files = NuGet.extract("Demo", "1.0.0", &archive).unwrap();
+
+        assert_eq!(
+            sorted_paths(&files),
+            [
+                ".signature.p7s",
+                "Demo.nuspec",
+                "lib",
+                "lib/net8.0",
+                "lib/net8.0/Demo.dll",
+                "package",
+                "package/readme.md",
+            ]
+        );
+        assert_eq!(files["lib/net8.0/Demo.dll"].bytes, b"lib/net8.0/Demo.dll");
+    }
+
+    #[test]
+    fn archive_urls_use_lower_cased_ids_and_versions() {
+        assert_eq!(
+            NuGet
+                .archive_url(NuGet.default_base_url(), "Newtonsoft.Json", "13.0.3-Beta1", None)
+                .unwrap(),
+            "https://api.nuget.org/v3-flatcontainer/newtonsoft.json/13.0.3-beta1/newtonsoft.json.13.0.3-beta1.nupkg"
+        );
+    }
+}
@@ -0,0 +1,232 @@
+use std::collections::{HashMap, HashSet};
+
+use serde::Deserialize;
+
+use super::Registry;
+use crate::error::DiffError;
+use crate::package::PackageArchive;
+use diffpack_core::archive::{extract_archive_bytes_with, strip_common_root};
+use diffpack_core::types::{FileMapEntry, FileType};
+
+pub struct Packagist;
+
+#[derive(Deserialize)]
+struct PackagistResponse {
+    packages: HashMap<String, Vec<PackagistVersion>>,
+}
+
+/// One entry of the repo-v2 version list. The list is minified: each entry only
+/// carries the fields that changed since the previous one, and a field that was
+/// removed is sent as the string `"__unset"`.
+#[derive(Deserialize)]
+struct PackagistVersion {
+    version: String,
+    #[serde(default)]
+    dist: Option<serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+struct PackagistDist {
+    url: String,
+    #[serde(default)]
+    reference: Option<String>,
+}
+
+impl Registry for Packagist {
+    fn default_base_url(&self) -> &'static str {
+        "https://repo.packagist.org"
+    }
+
+    fn metadata_url(&self, base: &str, pkg: &str, _version: &str) -> Option<String> {
+        Some(format!("{base}/p2/{}.json", pkg.to_lowercase()))
+    }
+
+    fn archive_url(
+        &self,
+        _base: &str,
+        pkg: &str,
+        version: &str,
+        metadata: Option<&[u8]>,
+    ) -> Result<String, DiffError> {
+        Ok(select_dist(pkg, version, metadata)?.url)
+    }
+
+    /// GitHub dist zips wrap everything in `<owner>-<repo>-<short sha>/`, which
+    /// changes on every release. That root is only stripped when its suffix
+    /// matches the dist reference, so a package whose zip genuinely has a
+    /// single top-level `src/` keeps it.
+    fn extract(
+        &self,
+        pkg: &str,
+        version: &str,
+        archive: &PackageArchive,
+    ) -> Result<HashMap<String, FileMapEntry>, DiffError> {
+        let files = extract_archive_bytes_with(&archive.bytes, false)?;
+        let reference = select_dist(pkg, version, archive.metadata.as_deref())?.reference;
+        Ok(strip_dist_root(files, reference.as_deref()))
+    }
+
+    fn is_manifest(&self, path: &str) -> bool {
+        path == "composer.json"
+    }
+}
+
+fn select_dist(
+    pkg: &str,
+    version: &str,
+    metadata: Option<&[u8]>,
+) -> Result<PackagistDist, DiffError> {
+    let metadata_bytes =
+        metadata.ok_or_else(|| DiffError::Corrupt("Missing Packagist metadata".to_string()))?;
+    let mut metadata: PackagistResponse = serde_json::from_slice(metadata_bytes)
+        .map_err(|err| DiffError::Corrupt(format!("Failed to parse Packagist metadata: {err}")))?;
+    let versions = metadata
+        .packages
+        .remove(&pkg.to_lowercase())
+        .ok_or_else(|| DiffError::NotFound(format!("Package {pkg} not found on Packagist")))?;
+
+    let mut dist = None;
+    for entry in versions {
+        match entry.dist {
+            Some(serde_json::Value::String(ref unset)) if unset == "__unset" => dist = None,
+            Some(value) => dist = Some(value),
+            None => {}
+        }
+        if same_version(&entry.version, version) {
+            let dist = dist.ok_or_else(|| {
+                DiffError::NoArtifact(format!("No dist archive for {pkg} {version}"))
+            })?;
+            return serde_json::from_value(dist).map_err(|err| {
+                DiffError::Corrupt(format!("Failed to parse Packagist dist: {err}"))
+            });
+        }
+    }
+
+    Err(DiffError::NotFound(format!(
+        "Version {version} of {pkg} not found on Packagist"
+    )))
+}
+
+/// Tags are usually `v1.2.3` but people type `1.2.3`, and vice versa.
+fn same_version(published: &str, requested: &str) -> bool {
+    published.trim_start_matches('v') == requested.trim_start_matches('v')
+}
+
+fn strip_dist_root(
+    files: HashMap<String, FileMapEntry>,
+    reference: Option<&str>,
+) -> HashMap<String, FileMapEntry> {
+    let Some(reference) = reference else {
+        return strip_common_root(files);
+    };
+
+    let top_level: HashSet<&str> = files
+        .keys()
+        .filter_map(|path| path.split('/').next())
+        .collect();
+    let is_dist_root = match top_level.iter().next() {
+        Some(root) if top_level.len() == 1 => {
+            matches!(
+                files.get(*root).map(|entry| &entry.file_type),
+                Some(FileType::Directory)
+            ) && root
+                .rsplit('-')
+                .next()
+                .is_some_and(|sha| !sha.is_empty() && reference.starts_with(sha))
+        }
+        _ => false,
+    };
+
+    if is_dist_root {
+        strip_common_root(files)
+    } else {
+        files
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use diffpack_core::archive::test_support::{sorted_paths, tree};
+
+    /// Minified repo-v2 metadata, newest first. 2.0.0 and 1.1.0 share a dist
+    /// URL only because the latter inherits it; 1.0.1 drops it and 1.0.0 sets
+    /// a new one.
+    const METADATA: &str = r#"{"minified":"composer/2.0","packages":{"acme/demo":[
+        {"version":"v2.0.0","name":"acme/demo","dist":{"type":"zip","url":"https://api.github.com/repos/acme/demo/zipball/2222222aaaa","reference":"2222222aaaa"}},
+        {"version":"v1.1.0"},
+        {"version":"v1.0.1","dist":"__unset"},
+        {"version":"v1.0.0","dist":{"type":"zip","url":"https://api.github.com/repos/acme/demo/zipball/0000000bbbb","reference":"0000000bbbb"}}
+    ]}}"#;
+
+    fn dist(pkg: &str, version: &str) -> Result<PackagistDist, DiffError> {
+        select_dist(pkg, version, Some(METADATA.as_bytes()))
+    }
+
+    #[test]
+    fn expands_minified_version_lists() {
+        let latest = dist("acme/demo", "2.0.0").unwrap();
+        assert_eq!(latest.reference.as_deref(), Some("2222222aaaa"));
+
+        let inherited = dist("acme/demo", "v1.1.0").unwrap();
+        assert_eq!(inherited.url, latest.url);
+
+        assert!(matches!(
+            dist("acme/demo", "1.0.1"),
+            Err(DiffError::NoArtifact(_))
+        ));
+        assert_eq!(
+            dist("acme/demo", "1.0.0").unwrap().reference.as_deref(),
+            Some("0000000bbbb")
+        );
+    }
+
+    #[test]
+    fn reports_unknown_packages_and_versions() {
+        assert!(dist("Acme/Demo", "2.0.0").is_ok());
+        assert!(matches!(
+            dist("acme/other", "2.0.0"),
+            Err(DiffError::NotFound(_))
+        ));
+        assert!(matches!(
+            dist("acme/demo", "3.0.0"),
+            Err(DiffError::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn strips_a_github_zipball_root_matching_the_reference() {
+        let files = tree(&[
+            "acme-demo-2222222/",
+            "acme-demo-2222222/composer.json",
+            "acme-demo-2222222/src/",
+            "acme-demo-2222222/src/Demo.php",
+        ]);
+
+        let files = strip_dist_root(files, Some("2222222aaaa"));
+
+        assert_eq!(sorted_paths(&files), ["composer.json", "src", "src/Demo.php"]);
+    }
+
+    #[test]
+    fn keeps_a_single_folder_that_is_not_the_zipball_root() {
+        let files = tree(&["src/", "src/Demo.php"]);
+        assert_eq!(
+            sorted_paths(&strip_dist_root(files, Some("2222222aaaa"))),
+            ["src", "src/Demo.php"]
+        );
+
+        // A root named after another commit is kept as well.
+        let files = tree(&["acme-demo-3333333/", "acme-demo-3333333/Demo.php"]);
+        assert_eq!(
+            sorted_paths(&strip_dist_root(files, Some("2222222aaaa"))),
+            ["acme-demo-3333333", "acme-demo-3333333/Demo.php"]
+        );
+    }
+
+    #[test]
+    fn strips_any_single_root_without_a_reference() {
+        let files = tree(&["demo/", "demo/Demo.php"]);
+        assert_eq!(sorted_paths(&strip_dist_root(files, None)), ["Demo.php"]);
+    }
+}
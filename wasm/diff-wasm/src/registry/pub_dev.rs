@@ -0,0 +1,112 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+
+use super::Registry;
+use crate::error::DiffError;
+use crate::package::PackageArchive;
+use diffpack_core::archive::extract_archive_bytes_with;
+use diffpack_core::types::FileMapEntry;
+
+pub struct PubDev;
+
+#[derive(Deserialize)]
+struct PubResponse {
+    versions: Vec<PubVersion>,
+}
+
+#[derive(Deserialize)]
+struct PubVersion {
+    version: String,
+    archive_url: String,
+}
+
+impl Registry for PubDev {
+    fn default_base_url(&self) -> &'static str {
+        "https://pub.dev"
+    }
+
+    fn metadata_url(&self, base: &str, pkg: &str, _version: &str) -> Option<String> {
+        Some(format!("{base}/api/packages/{pkg}"))
+    }
+
+    fn archive_url(
+        &self,
+        _base: &str,
+        pkg: &str,
+        version: &str,
+        metadata: Option<&[u8]>,
+    ) -> Result<String, DiffError> {
+        let metadata_bytes =
+            metadata.ok_or_else(|| DiffError::Corrupt("Missing pub.dev metadata".to_string()))?;
+        let metadata: PubResponse = serde_json::from_slice(metadata_bytes).map_err(|err| {
+            DiffError::Corrupt(format!("Failed to parse pub.dev metadata: {err}"))
+        })?;
+
+        metadata
+            .versions
+            .into_iter()
+            .find(|entry| entry.version == version)
+            .map(|entry| entry.archive_url)
+            .ok_or_else(|| {
+                DiffError::NotFound(format!("Version {version} of {pkg} not found on pub.dev"))
+            })
+    }
+
+    /// `pub publish` archives the package directory's contents with no
+    /// wrapping folder. `strip_common_root` would mistake a package that only
+    /// ships `lib/` for a wrapped one and flatten it, so nothing is stripped.
+    fn extract(
+        &self,
+        _pkg: &str,
+        _version: &str,
+        archive: &PackageArchive,
+    ) -> Result<HashMap<String, FileMapEntry>, DiffError> {
+        Ok(extract_archive_bytes_with(&archive.bytes, false)?)
+    }
+
+    fn is_manifest(&self, path: &str) -> bool {
+        path == "pubspec.yaml"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use diffpack_core::archive::extract_archive_bytes;
+    use diffpack_core::archive::test_support::{sorted_paths, tar_of};
+
+    /// A package whose files hold their own paths.
+    fn package(paths: &[&str]) -> PackageArchive {
+        let files: Vec<_> = paths.iter().map(|path| (*path, path.as_bytes())).collect();
+        PackageArchive {
+            bytes: tar_of(&files),
+            metadata: None,
+            integrity: None,
+        }
+    }
+
+    #[test]
+    fn keeps_a_lone_top_level_folder() {
+        let archive = package(&["lib/", "lib/demo.dart", "lib/src/", "lib/src/impl.dart"]);
+
+        let files = PubDev.extract("demo", "1.0.0", &archive).unwrap();
+
+        assert_eq!(
+            sorted_paths(&files),
+            ["lib", "lib/demo.dart", "lib/src", "lib/src/impl.dart"]
+        );
+        // The default extraction would have taken `lib/` for a wrapping folder.
+        let stripped = extract_archive_bytes(&archive.bytes).unwrap();
+        assert_eq!(sorted_paths(&stripped), ["demo.dart", "src", "src/impl.dart"]);
+    }
+
+    #[test]
+    fn keeps_a_full_package_layout() {
+        let archive = package(&["pubspec.yaml", "lib/", "lib/demo.dart"]);
+
+        let files = PubDev.extract("demo", "1.0.0", &archive).unwrap();
+
+        assert_eq!(sorted_paths(&files), ["lib", "lib/demo.dart", "pubspec.yaml"]);
+    }
+}
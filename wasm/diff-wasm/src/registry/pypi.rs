@@ -0,0 +1,121 @@
+use serde::Deserialize;
+
+use super::Registry;
+use crate::error::DiffError;
+use crate::integrity::Digest;
+
+pub struct PyPi;
+
+#[derive(Deserialize)]
+struct PyPiResponse {
+    urls: Vec<PyPiUrl>,
+}
+
+#[derive(Deserialize)]
+struct PyPiUrl {
+    url: String,
+    packagetype: String,
+    #[serde(default)]
+    digests: PyPiDigests,
+}
+
+#[derive(Default, Deserialize)]
+struct PyPiDigests {
+    sha256: Option<String>,
+}
+
+impl Registry for PyPi {
+    fn default_base_url(&self) -> &'static str {
+        "https://pypi.org"
+    }
+
+    fn metadata_url(&self, base: &str, pkg: &str, version: &str) -> Option<String> {
+        Some(format!("{base}/pypi/{pkg}/{version}/json"))
+    }
+
+    fn archive_url(
+        &self,
+        _base: &str,
+        _pkg: &str,
+        _version: &str,
+        metadata: Option<&[u8]>,
+    ) -> Result<String, DiffError> {
+        let metadata = parse_metadata(metadata)?;
+        Ok(select_pypi_sdist(&metadata.urls)?.url.clone())
+    }
+
+    /// The digest listed next to the file `archive_url` picked.
+    fn expected_digest(
+        &self,
+        _pkg: &str,
+        _version: &str,
+        metadata: Option<&[u8]>,
+        _integrity: Option<&[u8]>,
+    ) -> Option<Digest> {
+        let metadata = parse_metadata(metadata).ok()?;
+        let entry = select_pypi_sdist(&metadata.urls).ok()?;
+        Digest::sha256_hex(entry.digests.sha256.as_deref()?)
+    }
+
+    /// Sdists carry `PKG-INFO` plus whichever build config the project uses;
+    /// wheels carry `<name>.dist-info/METADATA`.
+    fn is_manifest(&self, path: &str) -> bool {
+        matches!(
+            path,
+            "pyproject.toml" | "setup.py" | "setup.cfg" | "PKG-INFO"
+        ) || path
+            .strip_suffix("/METADATA")
+            .is_some_and(|dir| !dir.contains('/') && dir.ends_with(".dist-info"))
+    }
+}
+
+fn parse_metadata(metadata: Option<&[u8]>) -> Result<PyPiResponse, DiffError> {
+    let metadata_bytes =
+        metadata.ok_or_else(|| DiffError::Corrupt("Missing PyPI metadata".to_string()))?;
+    serde_json::from_slice(metadata_bytes)
+        .map_err(|err| DiffError::Corrupt(format!("Failed to parse PyPI metadata: {err}")))
+}
+
+fn select_pypi_sdist(urls: &[PyPiUrl]) -> Result<&PyPiUrl, DiffError> {
+    let mut sdist_supported = None;
+    let mut sdist_fallback = None;
+    let mut wheel_supported = None;
+    let mut wheel_fallback = None;
+
+    for entry in urls {
+        if entry.packagetype == "sdist" {
+            if is_supported_archive_url(&entry.url) {
+                if sdist_supported.is_none() {
+                    sdist_supported = Some(entry);
+                }
+            } else if sdist_fallback.is_none() {
+                sdist_fallback = Some(entry);
+            }
+        } else if entry.packagetype == "bdist_wheel" {
+            if is_supported_archive_url(&entry.url) {
+                if wheel_supported.is_none() {
+                    wheel_supported = Some(entry);
+                }
+            } else if wheel_fallback.is_none() {
+                wheel_fallback = Some(entry);
+            }
+        }
+    }
+
+    sdist_supported
+        .or(wheel_supported)
+        .or(sdist_fallback)
+        .or(wheel_fallback)
+        .ok_or_else(|| {
+            DiffError::NoArtifact("No downloadable artifacts found for PyPI package".to_string())
+        })
+}
+
+fn is_supported_archive_url(url: &str) -> bool {
+    let lower = url.to_ascii_lowercase();
+    lower.ends_with(".tar.gz")
+        || lower.ends_with(".tgz")
+        || lower.ends_with(".tar")
+        || lower.ends_with(".zip")
+        || lower.ends_with(".whl")
+}
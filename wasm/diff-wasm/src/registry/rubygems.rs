@@ -0,0 +1,143 @@
+use std::collections::HashMap;
+
+use super::Registry;
+use crate::error::DiffError;
+use crate::package::PackageArchive;
+use diffpack_core::archive::{archive_member, insert_synthetic, Extraction};
+use diffpack_core::types::FileMapEntry;
+
+/// Where the decompressed gemspec is surfaced. It lives outside `data.tar.gz`,
+/// so it needs a path that cannot collide with the gem's own files.
+const GEMSPEC_PATH: &str = ".gem/metadata.yml";
+
+pub struct RubyGems;
+
+impl Registry for RubyGems {
+    fn default_base_url(&self) -> &'static str {
+        "https://rubygems.org"
+    }
+
+    fn archive_url(
+        &self,
+        base: &str,
+        pkg: &str,
+        version: &str,
+        _metadata: Option<&[u8]>,
+    ) -> Result<String, DiffError> {
+        Ok(format!("{base}/gems/{pkg}-{version}.gem"))
+    }
+
+    /// A `.gem` is an uncompressed tar holding `data.tar.gz` (the gem's files),
+    /// `metadata.gz` (the YAML gemspec) and `checksums.yaml.gz`. Only the first
+    /// two are worth diffing. The data archive has no root directory, so it is
+    /// extracted without `strip_common_root`, which would otherwise flatten gems
+    /// that only ship `lib/`. Both gzip members spend the `.gem`'s limits.
+    fn extract(
+        &self,
+        _pkg: &str,
+        _version: &str,
+        archive: &PackageArchive,
+    ) -> Result<HashMap<String, FileMapEntry>, DiffError> {
+        let mut extraction = Extraction::new(&archive.bytes)?;
+        let outer = extraction.extract(&archive.bytes, false)?;
+        let data = archive_member(&outer, "data.tar.gz")?;
+        let mut files = extraction.extract(data, false)?;
+
+        if outer.contains_key("metadata.gz") {
+            let metadata = extraction.gunzip(archive_member(&outer, "metadata.gz")?)?;
+            insert_synthetic(&mut files, GEMSPEC_PATH, FileMapEntry::file(metadata));
+        }
+
+        Ok(files)
+    }
+
+    fn is_manifest(&self, path: &str) -> bool {
+        path == GEMSPEC_PATH
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::DiffError;
+    use diffpack_core::archive::test_support::{gzip, tar_of};
+    use diffpack_core::limits::{set_limits, ExtractLimits};
+    use diffpack_core::types::{AnomalyKind, FileType};
+
+    fn gem(data: &[(&str, &[u8])], metadata: &[u8]) -> PackageArchive {
+        PackageArchive {
+            bytes: tar_of(&[
+                ("metadata.gz", &gzip(metadata)),
+                ("data.tar.gz", &gzip(&tar_of(data))),
+            ]),
+            metadata: None,
+            integrity: None,
+        }
+    }
+
+    #[test]
+    fn surfaces_the_gemspec_next_to_the_data() {
+        let archive = gem(&[("lib/demo.rb", b"module Demo; end\n")], b"name: demo\n");
+
+        let files = RubyGems.extract("demo", "1.0.0", &archive).unwrap();
+
+        assert_eq!(files["lib/demo.rb"].bytes, b"module Demo; end\n");
+        assert_eq!(files[GEMSPEC_PATH].bytes, b"name: demo\n");
+        assert!(files[GEMSPEC_PATH].anomalies.is_empty());
+        assert!(matches!(files[".gem"].file_type, FileType::Directory));
+    }
+
+    #[test]
+    fn flags_a_data_file_at_the_gemspec_path() {
+        let archive = gem(&[(GEMSPEC_PATH, b"name: innocent\n")], b"name: demo\n");
+
+        let files = RubyGems.extract("demo", "1.0.0", &archive).unwrap();
+
+        assert_eq!(files[GEMSPEC_PATH].bytes, b"name: demo\n");
+        let kinds: Vec<_> = files[GEMSPEC_PATH]
+            .anomalies
+            .iter()
+            .map(|anomaly| anomaly.kind)
+            .collect();
+        assert_eq!(kinds, [AnomalyKind::Duplicate]);
+    }
+
+    #[test]
+    fn reports_an_oversized_gemspec_as_too_large() {
+        // Incompressible, so metadata.gz ends up larger than data.tar.gz.
+        let mut state = 1u32;
+        let metadata: Vec<u8> = (0..4096)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                (state >> 16) as u8
+            })
+            .collect();
+        let archive = gem(&[("lib/demo.rb", b"")], &metadata);
+        let data_len = gzip(&tar_of(&[("lib/demo.rb", b"")])).len();
+        set_limits(ExtractLimits {
+            max_file_bytes: data_len as u64,
+            max_ratio: u64::MAX,
+            ..Default::default()
+        });
+
+        let err = RubyGems.extract("demo", "1.0.0", &archive).unwrap_err();
+
+        assert!(matches!(err, DiffError::LimitExceeded(_)), "{err}");
+        assert!(err.to_string().contains("metadata.gz"), "{err}");
+    }
+
+    #[test]
+    fn data_entries_count_against_the_gem_limit() {
+        let archive = gem(&[("lib/demo.rb", b"")], b"name: demo\n");
+        // The outer tar's two members fit, as does data.tar.gz's one on its
+        // own, but not all three.
+        set_limits(ExtractLimits {
+            max_entries: 2,
+            ..Default::default()
+        });
+
+        let err = RubyGems.extract("demo", "1.0.0", &archive).unwrap_err();
+
+        assert!(matches!(err, DiffError::LimitExceeded(_)), "{err}");
+    }
+}
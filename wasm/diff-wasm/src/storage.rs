@@ -0,0 +1,92 @@
+use js_sys::Uint8Array;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{Cache, CacheStorage, Response, Window, WorkerGlobalScope};
+
+/// Persistent home for raw package archives, keyed by `cache_key`. Published
+/// versions are immutable, so entries never need invalidating.
+pub trait ArchiveStore {
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, JsValue>;
+    async fn put(&self, key: &str, bytes: &[u8]) -> Result<(), JsValue>;
+}
+
+const CACHE_NAME: &str = "diffpack-archives-v1";
+
+/// Cache Storage only accepts http(s) requests as keys, so archives are stored
+/// under a synthetic URL that is never fetched.
+const KEY_URL_PREFIX: &str = "https://archives.diffpack.invalid/";
+
+/// The browser's Cache Storage, shared between tabs and surviving reloads.
+pub struct CacheStorageStore;
+
+impl CacheStorageStore {
+    async fn open(&self) -> Result<Cache, JsValue> {
+        let caches = cache_storage()?;
+        let cache = JsFuture::from(caches.open(CACHE_NAME)).await?;
+        cache.dyn_into()
+    }
+
+    fn key_url(key: &str) -> String {
+        format!(
+            "{KEY_URL_PREFIX}{}",
+            String::from(js_sys::encode_uri_component(key))
+        )
+    }
+}
+
+impl ArchiveStore for CacheStorageStore {
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, JsValue> {
+        let cache = self.open().await?;
+        let matched = JsFuture::from(cache.match_with_str(&Self::key_url(key))).await?;
+        if matched.is_undefined() {
+            return Ok(None);
+        }
+
+        let resp: Response = matched.dyn_into()?;
+        let buffer = JsFuture::from(resp.array_buffer()?).await?;
+        Ok(Some(Uint8Array::new(&buffer).to_vec()))
+    }
+
+    async fn put(&self, key: &str, bytes: &[u8]) -> Result<(), JsValue> {
+        let cache = self.open().await?;
+        let mut body = bytes.to_vec();
+        let resp = Response::new_with_opt_u8_array(Some(&mut body))?;
+        JsFuture::from(cache.put_with_str(&Self::key_url(key), &resp)).await?;
+        Ok(())
+    }
+}
+
+fn cache_storage() -> Result<CacheStorage, JsValue> {
+    let global = js_sys::global();
+    if let Some(window) = global.dyn_ref::<Window>() {
+        window.caches()
+    } else if let Some(worker) = global.dyn_ref::<WorkerGlobalScope>() {
+        worker.caches()
+    } else {
+        Err(JsValue::from_str(
+            "Global scope does not support Cache Storage",
+        ))
+    }
+}
+
+/// Process-local store, so tests can run without Cache Storage.
+#[cfg(test)]
+#[derive(Default)]
+pub struct MemoryStore {
+    archives: std::cell::RefCell<std::collections::HashMap<String, Vec<u8>>>,
+}
+
+#[cfg(test)]
+impl ArchiveStore for MemoryStore {
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, JsValue> {
+        Ok(self.archives.borrow().get(key).cloned())
+    }
+
+    async fn put(&self, key: &str, bytes: &[u8]) -> Result<(), JsValue> {
+        self.archives
+            .borrow_mut()
+            .insert(key.to_string(), bytes.to_vec());
+        Ok(())
+    }
+}
@@ -0,0 +1,759 @@
+//! Archive extraction shared by the registries, uploaded archives and the
+//! native CLI.
+
+use std::cell::Cell;
+use std::collections::{HashMap, HashSet};
+use std::io::{Cursor, Read};
+
+use flate2::read::GzDecoder;
+use tar::Archive;
+use zip::ZipArchive;
+
+use crate::limits::Budget;
+use crate::types::{AnomalyKind, ContentKind, FileMapEntry, FileType, PathAnomaly};
+use crate::Error;
+
+#[cfg(any(test, feature = "test-support"))]
+pub mod test_support;
+
+pub fn extract_archive_bytes(bytes: &[u8]) -> Result<HashMap<String, FileMapEntry>, Error> {
+    extract_archive_bytes_with(bytes, true)
+}
+
+/// Extracts within the thread's [`ExtractLimits`](crate::limits::ExtractLimits):
+/// an archive over the compressed, total, entry-count or ratio limit fails with
+/// [`Error::LimitExceeded`], and files over the per-file limit are kept as
+/// too-large entries.
+pub fn extract_archive_bytes_with(
+    bytes: &[u8],
+    strip_root: bool,
+) -> Result<HashMap<String, FileMapEntry>, Error> {
+    Extraction::new(bytes)?.extract(bytes, strip_root)
+}
+
+/// The allowance of one downloaded archive. Formats that wrap the package in
+/// a second archive (gems, Hex tarballs) extract the inner one through the
+/// same `Extraction`, so every layer spends the outer archive's limits rather
+/// than starting afresh.
+pub struct Extraction {
+    budget: Budget,
+}
+
+impl Extraction {
+    /// Starts the allowance for `archive`, failing if it is over the
+    /// compressed size limit.
+    pub fn new(archive: &[u8]) -> Result<Self, Error> {
+        Ok(Self {
+            budget: Budget::new(archive.len())?,
+        })
+    }
+
+    /// Extracts `bytes`, the downloaded archive or a member of it.
+    pub fn extract(
+        &mut self,
+        bytes: &[u8],
+        strip_root: bool,
+    ) -> Result<HashMap<String, FileMapEntry>, Error> {
+        let budget = &mut self.budget;
+        if is_gzip(bytes) {
+            let overrun = Cell::new(false);
+            let stream = budget.cap_stream(GzDecoder::new(bytes), &overrun);
+            let files = extract_gzip(stream, strip_root, budget);
+            if overrun.get() {
+                return Err(budget.exceeded());
+            }
+            return files;
+        }
+        if is_zip(bytes) {
+            return parse_zip_bytes(bytes, strip_root, budget);
+        }
+        parse_tar_or_reject(Cursor::new(bytes), bytes, strip_root, budget)
+    }
+
+    /// Decompresses a standalone gzip member, spending the allowance.
+    pub fn gunzip(&mut self, bytes: &[u8]) -> Result<Vec<u8>, Error> {
+        let decompressed = self
+            .budget
+            .decompress(GzDecoder::new(bytes), "Gzip decompression")?;
+        self.budget.spend(decompressed.len() as u64)?;
+        Ok(decompressed)
+    }
+}
+
+/// The tar inside is parsed as it decompresses, so the decompressed stream is
+/// never held in memory next to the files extracted from it. Only its first
+/// block is read ahead, to tell what it holds.
+fn extract_gzip(
+    mut stream: impl Read,
+    strip_root: bool,
+    budget: &mut Budget,
+) -> Result<HashMap<String, FileMapEntry>, Error> {
+    let gzip_error =
+        |err: std::io::Error| Error::Corrupt(format!("Gzip decompression failed: {err}"));
+    let mut head = Vec::new();
+    (&mut stream)
+        .take(512)
+        .read_to_end(&mut head)
+        .map_err(gzip_error)?;
+
+    // Each layer could expand again; nothing legitimate nests gzip.
+    if is_gzip(&head) {
+        return Err(Error::UnsupportedFormat(
+            "Nested gzip streams are not supported".to_string(),
+        ));
+    }
+    if is_zip(&head) {
+        // Zip needs random access, so it is the one layout still buffered.
+        let mut decompressed = head;
+        stream.read_to_end(&mut decompressed).map_err(gzip_error)?;
+        return parse_zip_bytes(&decompressed, strip_root, budget);
+    }
+    let mut tar = Cursor::new(&head).chain(stream);
+    let files = parse_tar_or_reject(&mut tar, &head, strip_root, budget)?;
+    // The tar's end marker comes before the gzip trailer; reading on to it
+    // still checks the CRC.
+    std::io::copy(&mut tar, &mut std::io::sink()).map_err(gzip_error)?;
+    Ok(files)
+}
+
+/// Plain tar has no leading magic. A failed parse of something without the
+/// ustar marker in `head` most likely was never a tar (an HTML error page,
+/// say).
+fn parse_tar_or_reject(
+    reader: impl Read,
+    head: &[u8],
+    strip_root: bool,
+    budget: &mut Budget,
+) -> Result<HashMap<String, FileMapEntry>, Error> {
+    parse_tar(reader, strip_root, budget).map_err(|err| match err {
+        Error::Corrupt(_) if !has_ustar_magic(head) => {
+            Error::UnsupportedFormat("Not a gzip, tar or zip archive".to_string())
+        }
+        err => err,
+    })
+}
+
+/// Bytes of one file inside an already extracted outer archive, for formats
+/// that wrap the package contents in a second archive (gems, Hex tarballs).
+pub fn archive_member<'a>(
+    files: &'a HashMap<String, FileMapEntry>,
+    path: &str,
+) -> Result<&'a [u8], Error> {
+    match files.get(path) {
+        Some(entry) if matches!(entry.kind, ContentKind::TooLarge { .. }) => Err(
+            Error::LimitExceeded(format!("{path} is over the per-file size limit")),
+        ),
+        Some(entry) if matches!(entry.file_type, FileType::File) => Ok(&entry.bytes),
+        _ => Err(Error::MissingMember(path.to_string())),
+    }
+}
+
+/// Decompresses a standalone gzip member, within the same limits as an
+/// archive.
+pub fn gunzip(bytes: &[u8]) -> Result<Vec<u8>, Error> {
+    Extraction::new(bytes)?.gunzip(bytes)
+}
+
+fn parse_tar(
+    reader: impl Read,
+    strip_root: bool,
+    budget: &mut Budget,
+) -> Result<HashMap<String, FileMapEntry>, Error> {
+    let mut archive = Archive::new(reader);
+    let mut files = HashMap::new();
+    let entries = archive
+        .entries()
+        .map_err(|err| Error::Corrupt(format!("Tar parsing failed: {err}")))?;
+
+    for entry in entries {
+        let entry = entry.map_err(|err| Error::Corrupt(format!("Tar entry error: {err}")))?;
+        budget.count_entry()?;
+        let entry_type = entry.header().entry_type();
+        let path = entry
+            .path()
+            .map_err(|err| Error::Corrupt(format!("Tar path error: {err}")))?;
+        let path = path.to_string_lossy().into_owned();
+
+        let mode = entry.header().mode().ok();
+
+        if entry_type.is_dir() {
+            insert_entry(&mut files, &path, FileMapEntry::directory());
+        } else if entry_type.is_file() {
+            let file = budget.read_file(entry, "Tar read")?;
+            insert_entry(&mut files, &path, file.with_mode(mode));
+        } else if entry_type.is_symlink() || entry_type.is_hard_link() {
+            let target = entry
+                .link_name()
+                .map_err(|err| Error::Corrupt(format!("Tar link error: {err}")))?
+                .map(|target| target.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            let link = if entry_type.is_symlink() {
+                FileMapEntry::symlink(target)
+            } else {
+                hard_link(&files, &target, budget)?
+            };
+            insert_entry(&mut files, &path, link.with_mode(mode));
+        }
+    }
+
+    ensure_directories(&mut files);
+    Ok(if strip_root {
+        strip_common_root(files)
+    } else {
+        files
+    })
+}
+
+fn parse_zip_bytes(
+    bytes: &[u8],
+    strip_root: bool,
+    budget: &mut Budget,
+) -> Result<HashMap<String, FileMapEntry>, Error> {
+    let reader = Cursor::new(bytes);
+    let mut archive = ZipArchive::new(reader)
+        .map_err(|err| Error::Corrupt(format!("Zip parsing failed: {err}")))?;
+    let mut files = HashMap::new();
+
+    for i in 0..archive.len() {
+        budget.count_entry()?;
+        let entry = archive
+            .by_index(i)
+            .map_err(|err| Error::Corrupt(format!("Zip entry error: {err}")))?;
+        let path = entry.name().to_string();
+
+        let mode = entry.unix_mode();
+
+        if entry.is_dir() {
+            insert_entry(&mut files, &path, FileMapEntry::directory());
+        } else if entry.is_symlink() {
+            // Zip stores a symlink as a file whose content is the target.
+            let file = budget.read_file(entry, "Zip read")?;
+            let target = String::from_utf8_lossy(&file.bytes).into_owned();
+            insert_entry(
+                &mut files,
+                &path,
+                FileMapEntry::symlink(target).with_mode(mode),
+            );
+        } else {
+            let file = budget.read_file(entry, "Zip read")?;
+            insert_entry(&mut files, &path, file.with_mode(mode));
+        }
+    }
+
+    ensure_directories(&mut files);
+    Ok(if strip_root {
+        strip_common_root(files)
+    } else {
+        files
+    })
+}
+
+/// A tar hard link becomes a copy of the file it names, which must come earlier
+/// in the archive. The copy spends the budget again, as extracting it would;
+/// a link to nothing (or to something later) becomes an empty file and is
+/// flagged.
+fn hard_link(
+    files: &HashMap<String, FileMapEntry>,
+    target: &str,
+    budget: &mut Budget,
+) -> Result<FileMapEntry, Error> {
+    let (resolved, kinds) = normalize_path(target);
+    let mut dangling = false;
+    let mut link = match files.get(&resolved) {
+        Some(entry) if matches!(entry.kind, ContentKind::TooLarge { .. }) => entry.clone(),
+        Some(entry) if matches!(entry.file_type, FileType::File) => {
+            budget.read_file(entry.bytes.as_slice(), "Tar hard link")?
+        }
+        _ => {
+            dangling = true;
+            FileMapEntry::file(Vec::new())
+        }
+    };
+    link.anomalies = Vec::new();
+    if dangling {
+        link.anomalies.push(PathAnomaly {
+            kind: AnomalyKind::DanglingLink,
+            raw_path: target.to_string(),
+        });
+    }
+    if !kinds.is_empty() {
+        link.anomalies.push(PathAnomaly {
+            kind: AnomalyKind::LinkEscape,
+            raw_path: target.to_string(),
+        });
+    }
+    link.link_target = Some(resolved);
+    Ok(link)
+}
+
+/// Adds an entry under its canonical path, recording how its raw name was
+/// suspicious. Entries that resolve to nothing (`/`, `..`) are dropped.
+fn insert_entry(
+    files: &mut HashMap<String, FileMapEntry>,
+    raw_path: &str,
+    mut entry: FileMapEntry,
+) {
+    let (path, mut kinds) = normalize_path(raw_path);
+    if path.is_empty() {
+        return;
+    }
+
+    if let Some(shadowed) = files.remove(&path) {
+        // A directory listed twice is harmless; anything else shadows content,
+        // including a symlink replacing a file or another symlink.
+        let is_dir = |entry: &FileMapEntry| matches!(entry.file_type, FileType::Directory);
+        if !is_dir(&shadowed) || !is_dir(&entry) {
+            kinds.push(AnomalyKind::Duplicate);
+        }
+        entry.anomalies.splice(0..0, shadowed.anomalies);
+    }
+    entry
+        .anomalies
+        .extend(kinds.into_iter().map(|kind| PathAnomaly {
+            kind,
+            raw_path: raw_path.to_string(),
+        }));
+    files.insert(path, entry);
+}
+
+/// Adds a file that a registry places next to the archive's own contents, such
+/// as a gemspec read from outside the data archive. An archive entry already at
+/// `path` is shadowed and flagged as a duplicate, so a crafted package cannot
+/// hide a file of its own behind the synthetic one.
+pub fn insert_synthetic(files: &mut HashMap<String, FileMapEntry>, path: &str, entry: FileMapEntry) {
+    insert_entry(files, path, entry);
+    ensure_directories(files);
+}
+
+/// Resolves an entry name the way an extractor would under its destination
+/// directory: backslashes become separators, empty and `.` components go, `..`
+/// removes the previous component but never climbs above the root, and a
+/// drive letter or leading separator is dropped. Also returns every anomaly
+/// found on the way.
+fn normalize_path(raw_path: &str) -> (String, Vec<AnomalyKind>) {
+    let mut kinds = Vec::new();
+    let mut path = raw_path;
+    if let Some(nul) = path.find('\0') {
+        kinds.push(AnomalyKind::NulByte);
+        path = &path[..nul];
+    }
+
+    let path = path.replace('\\', "/");
+    let mut rest = path.as_str();
+    let bytes = rest.as_bytes();
+    if bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':' {
+        rest = &rest[2..];
+        kinds.push(AnomalyKind::Absolute);
+    } else if rest.starts_with('/') {
+        kinds.push(AnomalyKind::Absolute);
+    }
+
+    let mut components: Vec<&str> = Vec::new();
+    for component in rest.split('/') {
+        match component {
+            "" | "." => {}
+            ".." => {
+                if !kinds.contains(&AnomalyKind::Traversal) {
+                    kinds.push(AnomalyKind::Traversal);
+                }
+                components.pop();
+            }
+            component => components.push(component),
+        }
+    }
+    (components.join("/"), kinds)
+}
+
+fn is_gzip(bytes: &[u8]) -> bool {
+    bytes.len() >= 2 && bytes[0] == 0x1f && bytes[1] == 0x8b
+}
+
+fn has_ustar_magic(bytes: &[u8]) -> bool {
+    bytes.get(257..262) == Some(b"ustar")
+}
+
+fn is_zip(bytes: &[u8]) -> bool {
+    bytes.len() >= 4
+        && ((bytes[0] == 0x50 && bytes[1] == 0x4b && bytes[2] == 0x03 && bytes[3] == 0x04)
+            || (bytes[0] == 0x50 && bytes[1] == 0x4b && bytes[2] == 0x05 && bytes[3] == 0x06)
+            || (bytes[0] == 0x50 && bytes[1] == 0x4b && bytes[2] == 0x07 && bytes[3] == 0x08))
+}
+
+pub fn ensure_directories(files: &mut HashMap<String, FileMapEntry>) {
+    let paths: Vec<String> = files.keys().cloned().collect();
+    for path in paths {
+        let mut current = String::new();
+        for part in path.split('/').take_while(|part| !part.is_empty()) {
+            if !current.is_empty() {
+                current.push('/');
+            }
+            current.push_str(part);
+            if !files.contains_key(&current) {
+                files.insert(current.clone(), FileMapEntry::directory());
+            }
+        }
+    }
+}
+
+pub fn strip_common_root(
+    mut files: HashMap<String, FileMapEntry>,
+) -> HashMap<String, FileMapEntry> {
+    let paths: Vec<String> = files.keys().cloned().collect();
+    if paths.is_empty() {
+        return files;
+    }
+
+    let mut top_level = HashSet::new();
+    for path in &paths {
+        if let Some(first) = path.split('/').next() {
+            if !first.is_empty() {
+                top_level.insert(first.to_string());
+            }
+        }
+    }
+
+    if top_level.len() != 1 {
+        return files;
+    }
+
+    let root = top_level.into_iter().next().unwrap();
+    match files.get(&root) {
+        Some(entry) if matches!(entry.file_type, FileType::Directory) => {}
+        _ => return files,
+    }
+
+    let prefix = format!("{root}/");
+    let mut new_files = HashMap::new();
+    let mut has_files = false;
+    for path in paths {
+        if path == root {
+            continue;
+        }
+        if let Some(new_path) = path.strip_prefix(&prefix) {
+            if !new_path.is_empty() {
+                if let Some(mut entry) = files.remove(&path) {
+                    // A hard link names its target from the archive root, unlike
+                    // a symlink's target, which is relative to the link.
+                    if entry.file_type == FileType::File {
+                        if let Some(target) = &entry.link_target {
+                            if let Some(stripped) = target.strip_prefix(&prefix) {
+                                entry.link_target = Some(stripped.to_string());
+                            }
+                        }
+                    }
+                    new_files.insert(new_path.to_string(), entry);
+                    has_files = true;
+                }
+            }
+        }
+    }
+
+    if has_files {
+        new_files
+    } else {
+        files
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tar::EntryType;
+
+    use super::test_support::{gzip, sorted_paths, tar_entries, tree};
+    use super::*;
+    use crate::limits::{set_limits, ExtractLimits};
+
+    fn limit(limits: ExtractLimits) {
+        set_limits(ExtractLimits {
+            max_ratio: u64::MAX,
+            ..limits
+        });
+    }
+
+    #[test]
+    fn nested_archives_share_the_outer_allowance() {
+        // The tar stream is 2048 bytes, which the cap must leave room for.
+        let inner = gzip(&tar_entries(&[("a", EntryType::Regular, b"123456")]));
+        let rest = vec![0; 2048 - 6];
+        limit(ExtractLimits {
+            max_total_bytes: 2048,
+            max_entries: 1,
+            ..Default::default()
+        });
+        assert!(extract_archive_bytes(&inner).is_ok());
+        assert!(gunzip(&gzip(&rest)).is_ok());
+
+        let mut extraction = Extraction::new(&inner).unwrap();
+        extraction.extract(&inner, false).unwrap();
+        let err = extraction.extract(&inner, false).unwrap_err();
+        assert!(matches!(err, Error::LimitExceeded(_)), "{err}");
+
+        let mut extraction = Extraction::new(&inner).unwrap();
+        extraction.extract(&inner, false).unwrap();
+        let err = extraction.gunzip(&gzip(&[rest, vec![0]].concat())).unwrap_err();
+        assert!(matches!(err, Error::LimitExceeded(_)), "{err}");
+    }
+
+    #[test]
+    fn streams_a_gzipped_tar() {
+        let tgz = gzip(&tar_entries(&[
+            ("pkg/a.txt", EntryType::Regular, b"a\n"),
+            ("pkg/b/c.txt", EntryType::Regular, b"c\n"),
+        ]));
+
+        let files = extract_archive_bytes(&tgz).unwrap();
+
+        assert_eq!(files["a.txt"].bytes, b"a\n");
+        assert_eq!(files["b/c.txt"].bytes, b"c\n");
+        assert!(matches!(files["b"].file_type, FileType::Directory));
+    }
+
+    #[test]
+    fn gzip_bomb_stops_at_the_total_limit() {
+        limit(ExtractLimits {
+            max_total_bytes: 1 << 20,
+            ..Default::default()
+        });
+        let zeros = vec![0; 4 << 20];
+        let tgz = gzip(&tar_entries(&[("bomb", EntryType::Regular, &zeros)]));
+        assert!(tgz.len() < 16 << 10);
+
+        let err = extract_archive_bytes(&tgz).unwrap_err();
+
+        assert!(matches!(err, Error::LimitExceeded(_)), "{err}");
+    }
+
+    #[test]
+    fn gzip_bomb_of_skipped_entries_stops_at_the_total_limit() {
+        // Entry kinds that are never extracted still have to be decompressed to
+        // be skipped, so only the cap on the stream stops them.
+        limit(ExtractLimits {
+            max_total_bytes: 1 << 20,
+            ..Default::default()
+        });
+        let zeros = vec![0; 4 << 20];
+        let tgz = gzip(&tar_entries(&[("fifo", EntryType::Fifo, &zeros)]));
+
+        let err = extract_archive_bytes(&tgz).unwrap_err();
+
+        assert!(matches!(err, Error::LimitExceeded(_)), "{err}");
+    }
+
+    #[test]
+    fn entry_count_is_limited() {
+        limit(ExtractLimits {
+            max_entries: 2,
+            ..Default::default()
+        });
+        let at_limit = tar_entries(&[
+            ("a", EntryType::Regular, b"a"),
+            ("b", EntryType::Regular, b"b"),
+        ]);
+        let over_limit = tar_entries(&[
+            ("a", EntryType::Regular, b"a"),
+            ("b", EntryType::Regular, b"b"),
+            ("c", EntryType::Regular, b"c"),
+        ]);
+
+        assert_eq!(extract_archive_bytes(&gzip(&at_limit)).unwrap().len(), 2);
+        let err = extract_archive_bytes(&gzip(&over_limit)).unwrap_err();
+        assert!(matches!(err, Error::LimitExceeded(_)), "{err}");
+    }
+
+    #[test]
+    fn files_over_the_per_file_limit_are_kept_as_too_large() {
+        limit(ExtractLimits {
+            max_file_bytes: 4,
+            ..Default::default()
+        });
+        let tgz = gzip(&tar_entries(&[
+            ("small", EntryType::Regular, b"1234"),
+            ("large", EntryType::Regular, b"12345"),
+        ]));
+
+        let files = extract_archive_bytes_with(&tgz, false).unwrap();
+
+        assert_eq!(files["small"].bytes, b"1234");
+        assert!(matches!(
+            files["large"].kind,
+            ContentKind::TooLarge { size: 5, .. }
+        ));
+    }
+
+    #[test]
+    fn normalizes_entry_names() {
+        use AnomalyKind::*;
+        let cases: &[(&str, &str, &[AnomalyKind])] = &[
+            ("a/b.txt", "a/b.txt", &[]),
+            ("./a//b/./c", "a/b/c", &[]),
+            ("a/", "a", &[]),
+            ("", "", &[]),
+            ("a/../b", "b", &[Traversal]),
+            ("a/../../b", "b", &[Traversal]),
+            ("../../etc/passwd", "etc/passwd", &[Traversal]),
+            ("..", "", &[Traversal]),
+            ("/etc/passwd", "etc/passwd", &[Absolute]),
+            ("//server/share", "server/share", &[Absolute]),
+            ("/../x", "x", &[Absolute, Traversal]),
+            ("C:\\x", "x", &[Absolute]),
+            ("c:/x", "x", &[Absolute]),
+            ("C:x", "x", &[Absolute]),
+            ("a\\b\\c", "a/b/c", &[]),
+            ("\\x", "x", &[Absolute]),
+            ("..\\..\\x", "x", &[Traversal]),
+            ("a\0.txt/b", "a", &[NulByte]),
+            ("a/b\0/../../../x", "a/b", &[NulByte]),
+        ];
+        for (raw, path, kinds) in cases {
+            assert_eq!(
+                normalize_path(raw),
+                (path.to_string(), kinds.to_vec()),
+                "{raw:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn names_that_normalize_alike_are_duplicates() {
+        for (first, second) in [
+            ("a/b", "./a//b"),
+            ("a/b", "a\\b"),
+            ("a/b", "/a/b"),
+            ("a/b", "a/c/../b"),
+            ("a/b", "a/b\0.sh"),
+        ] {
+            let mut files = HashMap::new();
+            insert_entry(&mut files, first, FileMapEntry::file(b"1".to_vec()));
+            insert_entry(&mut files, second, FileMapEntry::file(b"2".to_vec()));
+
+            assert_eq!(files.len(), 1, "{second:?}");
+            assert_eq!(files["a/b"].bytes, b"2");
+            let duplicate = PathAnomaly {
+                kind: AnomalyKind::Duplicate,
+                raw_path: second.to_string(),
+            };
+            assert!(files["a/b"].anomalies.contains(&duplicate), "{second:?}");
+        }
+    }
+
+    #[test]
+    fn entries_resolving_to_the_root_are_dropped() {
+        let mut files = HashMap::new();
+        for raw in ["/", "..", "./", "C:\\"] {
+            insert_entry(&mut files, raw, FileMapEntry::file(Vec::new()));
+        }
+        assert!(files.is_empty());
+    }
+
+    #[test]
+    fn strips_a_single_root_directory() {
+        let files = strip_common_root(tree(&["pkg/", "pkg/a", "pkg/src/", "pkg/src/b"]));
+        assert_eq!(sorted_paths(&files), ["a", "src", "src/b"]);
+    }
+
+    #[test]
+    fn keeps_paths_without_a_single_root_directory() {
+        for paths in [
+            &["a/", "a/x", "b/", "b/y"][..],
+            &["a/", "a/x", "top-level"],
+            &["only-a-file"],
+            &["empty/"],
+        ] {
+            let files = strip_common_root(tree(paths));
+            let mut expected: Vec<&str> = paths
+                .iter()
+                .map(|path| path.trim_end_matches('/'))
+                .collect();
+            expected.sort();
+            assert_eq!(sorted_paths(&files), expected);
+        }
+    }
+
+    fn duplicate_flagged(entries: &[(&str, EntryType, &[u8])]) -> bool {
+        let files = extract_archive_bytes_with(&tar_entries(entries), false).unwrap();
+        files["x"]
+            .anomalies
+            .iter()
+            .any(|anomaly| anomaly.kind == AnomalyKind::Duplicate)
+    }
+
+    #[test]
+    fn flags_duplicates_unless_both_are_directories() {
+        assert!(duplicate_flagged(&[
+            ("x", EntryType::Regular, b"1"),
+            ("x", EntryType::Regular, b"2"),
+        ]));
+        assert!(duplicate_flagged(&[
+            ("x", EntryType::Directory, b""),
+            ("x", EntryType::Regular, b"2"),
+        ]));
+        assert!(duplicate_flagged(&[
+            ("x", EntryType::Symlink, b"a"),
+            ("x", EntryType::Symlink, b"b"),
+        ]));
+        assert!(duplicate_flagged(&[
+            ("x", EntryType::Directory, b""),
+            ("x", EntryType::Symlink, b"b"),
+        ]));
+        assert!(!duplicate_flagged(&[
+            ("x", EntryType::Directory, b""),
+            ("x/", EntryType::Directory, b""),
+        ]));
+    }
+
+    #[test]
+    fn hard_links_copy_an_earlier_file_relative_to_the_stripped_root() {
+        let tar = tar_entries(&[
+            ("pkg/a.txt", EntryType::Regular, b"a"),
+            ("pkg/b.txt", EntryType::Link, b"pkg/a.txt"),
+        ]);
+
+        let files = extract_archive_bytes(&tar).unwrap();
+
+        assert_eq!(files["b.txt"].bytes, b"a");
+        assert_eq!(files["b.txt"].link_target.as_deref(), Some("a.txt"));
+        assert!(files["b.txt"].anomalies.is_empty());
+    }
+
+    #[test]
+    fn flags_dangling_and_forward_hard_links() {
+        let tar = tar_entries(&[
+            ("pkg/forward.txt", EntryType::Link, b"pkg/later.txt"),
+            ("pkg/later.txt", EntryType::Regular, b"later"),
+            ("pkg/dangling.txt", EntryType::Link, b"pkg/missing.txt"),
+        ]);
+
+        let files = extract_archive_bytes(&tar).unwrap();
+
+        for (path, target) in [
+            ("forward.txt", "pkg/later.txt"),
+            ("dangling.txt", "pkg/missing.txt"),
+        ] {
+            assert!(files[path].bytes.is_empty());
+            assert_eq!(
+                files[path].anomalies,
+                [PathAnomaly {
+                    kind: AnomalyKind::DanglingLink,
+                    raw_path: target.to_string(),
+                }]
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_a_gzipped_non_archive() {
+        let err = extract_archive_bytes(&gzip(b"<html>Not found</html>")).unwrap_err();
+        assert!(matches!(err, Error::UnsupportedFormat(_)), "{err}");
+    }
+
+    #[test]
+    fn rejects_a_corrupted_gzip_trailer() {
+        let mut tgz = gzip(&tar_entries(&[("a", EntryType::Regular, b"a")]));
+        let crc = tgz.len() - 8;
+        tgz[crc] ^= 0xff;
+
+        let err = extract_archive_bytes(&tgz).unwrap_err();
+
+        assert!(matches!(err, Error::Corrupt(_)), "{err}");
+    }
+}
@@ -0,0 +1,103 @@
+//! Archive and tree builders for tests, here and in the crates that wrap this
+//! one. Enabled by the `test-support` feature.
+
+use std::collections::HashMap;
+use std::io::{Cursor, Write};
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use tar::{EntryType, Header};
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::types::FileMapEntry;
+
+/// A tar of regular files with mode 0644. A path ending in `/` becomes a
+/// directory with mode 0755.
+pub fn tar_of(files: &[(&str, &[u8])]) -> Vec<u8> {
+    let entries: Vec<_> = files
+        .iter()
+        .map(|(path, bytes)| {
+            let entry_type = if path.ends_with('/') {
+                EntryType::Directory
+            } else {
+                EntryType::Regular
+            };
+            (*path, entry_type, *bytes)
+        })
+        .collect();
+    tar_entries(&entries)
+}
+
+/// A tar of entries of any type. Links take their target from the bytes.
+pub fn tar_entries(entries: &[(&str, EntryType, &[u8])]) -> Vec<u8> {
+    let mut builder = tar::Builder::new(Vec::new());
+    for (path, entry_type, bytes) in entries {
+        let mut header = Header::new_gnu();
+        header.set_entry_type(*entry_type);
+        match entry_type {
+            EntryType::Symlink | EntryType::Link => {
+                header.set_mode(0o644);
+                header.set_size(0);
+                let target = std::str::from_utf8(bytes).unwrap();
+                builder.append_link(&mut header, path, target).unwrap();
+            }
+            EntryType::Directory => {
+                header.set_mode(0o755);
+                header.set_size(0);
+                builder.append_data(&mut header, path, &[][..]).unwrap();
+            }
+            _ => {
+                header.set_mode(0o644);
+                header.set_size(bytes.len() as u64);
+                builder.append_data(&mut header, path, *bytes).unwrap();
+            }
+        }
+    }
+    builder.into_inner().unwrap()
+}
+
+pub fn gzip(bytes: &[u8]) -> Vec<u8> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::best());
+    encoder.write_all(bytes).unwrap();
+    encoder.finish().unwrap()
+}
+
+/// A deflated zip of files with mode 0644.
+pub fn zip_of(files: &[(&str, &[u8])]) -> Vec<u8> {
+    let files: Vec<_> = files
+        .iter()
+        .map(|(path, bytes)| (*path, *bytes, 0o644))
+        .collect();
+    zip_with_modes(&files)
+}
+
+/// A deflated zip of `(path, contents, unix mode)` files.
+pub fn zip_with_modes(files: &[(&str, &[u8], u32)]) -> Vec<u8> {
+    let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+    for (path, bytes, mode) in files {
+        let options = SimpleFileOptions::default()
+            .compression_method(CompressionMethod::Deflated)
+            .unix_permissions(*mode);
+        writer.start_file(*path, options).unwrap();
+        writer.write_all(bytes).unwrap();
+    }
+    writer.finish().unwrap().into_inner()
+}
+
+/// An extracted tree of empty files. A path ending in `/` becomes a directory.
+pub fn tree(paths: &[&str]) -> HashMap<String, FileMapEntry> {
+    paths
+        .iter()
+        .map(|path| match path.strip_suffix('/') {
+            Some(dir) => (dir.to_string(), FileMapEntry::directory()),
+            None => (path.to_string(), FileMapEntry::file(Vec::new())),
+        })
+        .collect()
+}
+
+pub fn sorted_paths(files: &HashMap<String, FileMapEntry>) -> Vec<&str> {
+    let mut paths: Vec<&str> = files.keys().map(String::as_str).collect();
+    paths.sort();
+    paths
+}
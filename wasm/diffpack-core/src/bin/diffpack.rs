@@ -0,0 +1,581 @@
+//! `diffpack` compares two package archives or directories from the command
+//! line, using the same extraction and diffing code as the web app.
+//!
+//! Exit status follows diff(1): 0 when both sides are identical, 1 when they
+//! differ and 2 when something went wrong.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write as _;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::process::ExitCode;
+
+use diffpack_core::archive::extract_archive_bytes;
+use diffpack_core::diff::{build_diff_tree, get_diff_hunks, HunkOptions};
+use diffpack_core::limits::UnpackedBudget;
+use diffpack_core::patch::{
+    build_patch, collect_rename_sources, PatchOptions, DEFAULT_CONTEXT_LINES,
+};
+use diffpack_core::types::{
+    AnomalyKind, DiffFileEntry, DiffStatus, FileMapEntry, FileType, LineTag,
+};
+
+const USAGE: &str = "\
+Usage: diffpack [OPTIONS] <OLD> <NEW>
+
+Compares two package archives (.tgz, .tar, .zip, .crate, ...) or directories.
+
+Options:
+      --summary            List changed files with line counts (default)
+      --diff               Print the changed lines of every file with line numbers
+      --patch              Print a unified diff that git apply accepts
+      --binary             Include binary files in --patch as git binary patches
+  -U, --context <LINES>    Context lines around changes [default: 3]
+      --similarity <RATIO> Minimum similarity for rename detection [default: 0.75]
+  -h, --help               Print this help
+
+Exit status is 0 if nothing changed, 1 if something did and 2 on error.";
+
+#[derive(Clone, Copy)]
+enum Mode {
+    Summary,
+    Diff,
+    Patch,
+}
+
+struct Args {
+    mode: Mode,
+    context_lines: usize,
+    binary_literals: bool,
+    similarity_threshold: f64,
+    old: String,
+    new: String,
+}
+
+fn main() -> ExitCode {
+    ExitCode::from(cli(std::env::args().skip(1), &mut io::stdout().lock()))
+}
+
+/// Runs one invocation, writing the comparison to `out`, and returns its exit
+/// status.
+fn cli(raw: impl Iterator<Item = String>, out: &mut impl Write) -> u8 {
+    let args = match parse_args(raw) {
+        Ok(Some(args)) => args,
+        Ok(None) => {
+            let _ = writeln!(out, "{USAGE}");
+            return 0;
+        }
+        Err(err) => {
+            eprintln!("diffpack: {err}\n\n{USAGE}");
+            return 2;
+        }
+    };
+
+    match run(&args, out) {
+        Ok(false) => 0,
+        Ok(true) => 1,
+        Err(err) => {
+            eprintln!("diffpack: {err}");
+            2
+        }
+    }
+}
+
+fn parse_args(mut raw: impl Iterator<Item = String>) -> Result<Option<Args>, String> {
+    let mut mode = Mode::Summary;
+    let mut context_lines = DEFAULT_CONTEXT_LINES;
+    let mut binary_literals = false;
+    let mut similarity_threshold = 0.75;
+    let mut paths = Vec::new();
+
+    while let Some(arg) = raw.next() {
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "--summary" => mode = Mode::Summary,
+            "--diff" => mode = Mode::Diff,
+            "--patch" => mode = Mode::Patch,
+            "--binary" => binary_literals = true,
+            "-U" | "--context" => {
+                let value = raw.next().ok_or("--context needs a value")?;
+                context_lines = value
+                    .parse()
+                    .map_err(|_| format!("invalid context line count: {value}"))?;
+            }
+            "--similarity" => {
+                let value = raw.next().ok_or("--similarity needs a value")?;
+                similarity_threshold = value
+                    .parse()
+                    .map_err(|_| format!("invalid similarity ratio: {value}"))?;
+            }
+            flag if flag.starts_with('-') && flag != "-" => {
+                return Err(format!("unknown option: {flag}"));
+            }
+            _ => paths.push(arg),
+        }
+    }
+
+    let [old, new]: [String; 2] = paths
+        .try_into()
+        .map_err(|_| "expected exactly two paths to compare".to_string())?;
+    Ok(Some(Args {
+        mode,
+        context_lines,
+        binary_literals,
+        similarity_threshold,
+        old,
+        new,
+    }))
+}
+
+/// Prints the comparison and reports whether anything changed.
+fn run(args: &Args, out: &mut impl Write) -> Result<bool, String> {
+    let from_files = load(Path::new(&args.old))?;
+    let to_files = load(Path::new(&args.new))?;
+    let tree = build_diff_tree(&from_files, &to_files, args.similarity_threshold);
+
+    let mut rename_sources = HashSet::new();
+    collect_rename_sources(&tree, &mut rename_sources);
+    let mut changed = Vec::new();
+    collect_changes(&tree, &rename_sources, &mut changed);
+
+    let output = match args.mode {
+        Mode::Summary => render_summary(&changed, &tree),
+        Mode::Diff => render_diffs(&changed, &from_files, &to_files, args.context_lines),
+        Mode::Patch => {
+            let options = PatchOptions {
+                context_lines: args.context_lines,
+                binary_literals: args.binary_literals,
+                lossy_text: false,
+            };
+            build_patch(&tree, &from_files, &to_files, options)
+        }
+    };
+    // A closed pipe (`diffpack ... | head`) is not an error worth reporting.
+    match out.write_all(output.as_bytes()) {
+        Err(err) if err.kind() != io::ErrorKind::BrokenPipe => {
+            return Err(format!("cannot write output: {err}"));
+        }
+        _ => {}
+    }
+
+    Ok(!changed.is_empty())
+}
+
+fn load(path: &Path) -> Result<HashMap<String, FileMapEntry>, String> {
+    let metadata =
+        fs::metadata(path).map_err(|err| format!("cannot read {}: {err}", path.display()))?;
+    if metadata.is_dir() {
+        let mut files = HashMap::new();
+        read_dir(path, "", &mut files, &mut UnpackedBudget::new())?;
+        Ok(files)
+    } else {
+        let bytes =
+            fs::read(path).map_err(|err| format!("cannot read {}: {err}", path.display()))?;
+        extract_archive_bytes(&bytes).map_err(|err| format!("{}: {err}", path.display()))
+    }
+}
+
+/// Loads a directory the way an archive of it would extract, within the same
+/// limits: files over the per-file limit are kept as too-large entries, and
+/// too many entries or bytes in total fail the load. `.git` is skipped so a
+/// working copy can be compared against a packed release, and symlinks are
+/// recorded rather than followed.
+fn read_dir(
+    dir: &Path,
+    prefix: &str,
+    files: &mut HashMap<String, FileMapEntry>,
+    budget: &mut UnpackedBudget,
+) -> Result<(), String> {
+    let entries =
+        fs::read_dir(dir).map_err(|err| format!("cannot read {}: {err}", dir.display()))?;
+    for entry in entries {
+        let entry = entry.map_err(|err| format!("cannot read {}: {err}", dir.display()))?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name == ".git" {
+            continue;
+        }
+        budget
+            .count_entry()
+            .map_err(|err| format!("{}: {err}", entry.path().display()))?;
+
+        let path = format!("{prefix}{name}");
+        let file_type = entry
+            .file_type()
+            .map_err(|err| format!("cannot read {}: {err}", entry.path().display()))?;
+        if file_type.is_dir() {
+            files.insert(path.clone(), FileMapEntry::directory());
+            read_dir(&entry.path(), &format!("{path}/"), files, budget)?;
+        } else if file_type.is_symlink() {
+            let target = fs::read_link(entry.path())
+                .map_err(|err| format!("cannot read {}: {err}", entry.path().display()))?;
+            let target = target.to_string_lossy().into_owned();
+            files.insert(path, FileMapEntry::symlink(target));
+        } else if file_type.is_file() {
+            let file = fs::File::open(entry.path())
+                .map_err(|err| format!("cannot read {}: {err}", entry.path().display()))?;
+            let metadata = file
+                .metadata()
+                .map_err(|err| format!("cannot read {}: {err}", entry.path().display()))?;
+            let file = budget
+                .read_file(file, "Read")
+                .map_err(|err| format!("{}: {err}", entry.path().display()))?;
+            files.insert(path, file.with_mode(file_mode(&metadata)));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn file_mode(metadata: &fs::Metadata) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+    Some(metadata.permissions().mode())
+}
+
+#[cfg(not(unix))]
+fn file_mode(_metadata: &fs::Metadata) -> Option<u32> {
+    None
+}
+
+/// Changed files in tree order. A rename's source is left out, since the
+/// rename entry already accounts for it.
+fn collect_changes<'a>(
+    node: &'a DiffFileEntry,
+    rename_sources: &HashSet<&str>,
+    changed: &mut Vec<&'a DiffFileEntry>,
+) {
+    match node.file_type {
+        FileType::Directory => {
+            for child in node.children.iter().flatten() {
+                collect_changes(child, rename_sources, changed);
+            }
+        }
+        FileType::File | FileType::Symlink => match node.status {
+            DiffStatus::Unchanged => {}
+            DiffStatus::Removed if rename_sources.contains(node.path.as_str()) => {}
+            _ => changed.push(node),
+        },
+    }
+}
+
+fn render_summary(changed: &[&DiffFileEntry], tree: &DiffFileEntry) -> String {
+    let mut out = String::new();
+    let (mut added, mut removed) = (0, 0);
+    for node in changed {
+        let status = match node.status {
+            DiffStatus::Added => 'A',
+            DiffStatus::Removed => 'D',
+            DiffStatus::Modified => 'M',
+            DiffStatus::Renamed => 'R',
+            DiffStatus::ModeChanged => 'X',
+            DiffStatus::LinkChanged => 'L',
+            DiffStatus::Unchanged => ' ',
+        };
+        let path = match &node.old_path {
+            Some(old_path) => format!("{old_path} -> {}", node.path),
+            None => node.path.clone(),
+        };
+        let mut counts = if node.file_type == FileType::Symlink {
+            format!("-> {}", node.link_target.as_deref().unwrap_or(""))
+        } else if node.too_large {
+            "too large".to_string()
+        } else if node.binary {
+            "binary".to_string()
+        } else {
+            let (plus, minus) = (node.added.unwrap_or(0), node.removed.unwrap_or(0));
+            added += plus;
+            removed += minus;
+            format!("+{plus} -{minus}")
+        };
+        if let Some(modes) = mode_change(node) {
+            let _ = write!(counts, "  {modes}");
+        }
+        let _ = writeln!(out, "{status}  {path}  {counts}");
+    }
+    let _ = writeln!(
+        out,
+        "{} files changed, {added} insertions(+), {removed} deletions(-)",
+        changed.len()
+    );
+
+    let mut anomalous = Vec::new();
+    collect_anomalous(tree, &mut anomalous);
+    if !anomalous.is_empty() {
+        let _ = writeln!(out, "\nsuspicious entry names:");
+    }
+    for node in anomalous {
+        for anomaly in &node.anomalies {
+            let kind = match anomaly.kind {
+                AnomalyKind::Absolute => "absolute path",
+                AnomalyKind::Traversal => "path traversal",
+                AnomalyKind::NulByte => "NUL byte",
+                AnomalyKind::Duplicate => "duplicate entry",
+                AnomalyKind::LinkEscape => "link outside package",
+                AnomalyKind::DanglingLink => "hard link to nothing",
+            };
+            let _ = writeln!(out, "!  {}  {kind}: {:?}", node.path, anomaly.raw_path);
+        }
+    }
+    out
+}
+
+/// `mode 644 -> 755` when the execute or special bits changed.
+fn mode_change(node: &DiffFileEntry) -> Option<String> {
+    let (old_mode, new_mode) = (node.old_mode?, node.new_mode?);
+    Some(format!("mode {old_mode:o} -> {new_mode:o}"))
+}
+
+/// Every entry, changed or not, whose name was suspicious in either version.
+fn collect_anomalous<'a>(node: &'a DiffFileEntry, anomalous: &mut Vec<&'a DiffFileEntry>) {
+    if !node.anomalies.is_empty() {
+        anomalous.push(node);
+    }
+    for child in node.children.iter().flatten() {
+        collect_anomalous(child, anomalous);
+    }
+}
+
+fn render_diffs(
+    changed: &[&DiffFileEntry],
+    from_files: &HashMap<String, FileMapEntry>,
+    to_files: &HashMap<String, FileMapEntry>,
+    context_lines: usize,
+) -> String {
+    let mut out = String::new();
+    let options = HunkOptions {
+        context_lines: Some(context_lines),
+        inline: None,
+    };
+    for node in changed {
+        let old_path = node.old_path.as_deref().unwrap_or(&node.path);
+        match &node.old_path {
+            Some(old_path) => {
+                let _ = writeln!(out, "=== {} (renamed from {old_path}) ===", node.path);
+            }
+            None => {
+                let _ = writeln!(out, "=== {} ===", node.path);
+            }
+        }
+        if let Some(modes) = mode_change(node) {
+            let _ = writeln!(out, "{modes}");
+        }
+        if node.too_large {
+            out.push_str("Files too large to display differ\n\n");
+            continue;
+        }
+        if node.binary {
+            out.push_str("Binary files differ\n\n");
+            continue;
+        }
+
+        let old_text = from_files
+            .get(old_path)
+            .and_then(FileMapEntry::display_text)
+            .unwrap_or_default();
+        let new_text = to_files
+            .get(&node.path)
+            .and_then(FileMapEntry::display_text)
+            .unwrap_or_default();
+        for hunk in get_diff_hunks(&old_text, &new_text, options) {
+            let _ = writeln!(
+                out,
+                "@@ -{},{} +{},{} @@",
+                hunk.old_start, hunk.old_lines, hunk.new_start, hunk.new_lines
+            );
+            for line in hunk.lines {
+                let sign = match line.tag {
+                    LineTag::Equal => ' ',
+                    LineTag::Insert => '+',
+                    LineTag::Delete => '-',
+                };
+                let number = |n: Option<u32>| n.map_or(String::new(), |n| n.to_string());
+                let _ = writeln!(
+                    out,
+                    "{:>6} {:>6} {sign} {}",
+                    number(line.old_line),
+                    number(line.new_line),
+                    line.content
+                );
+                if line.no_newline {
+                    out.push_str("\\ No newline at end of file\n");
+                }
+            }
+        }
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use diffpack_core::archive::test_support::tar_of;
+    use diffpack_core::limits::{self, ExtractLimits};
+
+    use super::*;
+
+    fn args(raw: &[&str]) -> Result<Option<Args>, String> {
+        parse_args(raw.iter().map(|arg| arg.to_string()))
+    }
+
+    /// Runs the CLI and returns its exit status and output.
+    fn run_cli(raw: &[&str]) -> (u8, String) {
+        let mut out = Vec::new();
+        let status = cli(raw.iter().map(|arg| arg.to_string()), &mut out);
+        (status, String::from_utf8(out).unwrap())
+    }
+
+    fn write_tree(dir: &Path, files: &[(&str, &str)]) {
+        for (path, contents) in files {
+            let path = dir.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+    }
+
+    #[test]
+    fn parses_modes_and_options() {
+        let parsed = args(&["--patch", "--binary", "-U", "5", "--similarity", "0.5", "a", "b"])
+            .unwrap()
+            .unwrap();
+
+        assert!(matches!(parsed.mode, Mode::Patch));
+        assert!(parsed.binary_literals);
+        assert_eq!(parsed.context_lines, 5);
+        assert_eq!(parsed.similarity_threshold, 0.5);
+        assert_eq!((parsed.old.as_str(), parsed.new.as_str()), ("a", "b"));
+    }
+
+    #[test]
+    fn defaults_to_a_summary() {
+        let parsed = args(&["a", "b"]).unwrap().unwrap();
+
+        assert!(matches!(parsed.mode, Mode::Summary));
+        assert!(!parsed.binary_literals);
+        assert_eq!(parsed.context_lines, DEFAULT_CONTEXT_LINES);
+        assert_eq!(parsed.similarity_threshold, 0.75);
+    }
+
+    #[test]
+    fn asks_for_help_without_paths() {
+        assert!(args(&["--help"]).unwrap().is_none());
+        assert!(args(&["a", "-h"]).unwrap().is_none());
+
+        let (status, output) = run_cli(&["--help"]);
+        assert_eq!(status, 0);
+        assert_eq!(output, format!("{USAGE}\n"));
+    }
+
+    #[test]
+    fn rejects_bad_arguments() {
+        let cases: &[(&[&str], &str)] = &[
+            (&["--frobnicate", "a", "b"], "unknown option: --frobnicate"),
+            (&["a", "b", "-U"], "--context needs a value"),
+            (&["-U", "three", "a", "b"], "invalid context line count: three"),
+            (&["-U", "-1", "a", "b"], "invalid context line count: -1"),
+            (&["a", "b", "--similarity"], "--similarity needs a value"),
+            (&["--similarity", "high", "a", "b"], "invalid similarity ratio: high"),
+            (&["a"], "expected exactly two paths to compare"),
+            (&["a", "b", "c"], "expected exactly two paths to compare"),
+        ];
+        for (raw, expected) in cases {
+            match args(raw) {
+                Err(err) => assert_eq!(err, *expected, "{raw:?}"),
+                Ok(_) => panic!("{raw:?} was accepted"),
+            }
+        }
+    }
+
+    #[test]
+    fn exits_0_for_identical_trees() {
+        let dir = tempfile::tempdir().unwrap();
+        for side in ["old", "new"] {
+            write_tree(&dir.path().join(side), &[("src/lib.rs", "fn a() {}\n")]);
+        }
+        let (old, new) = (dir.path().join("old"), dir.path().join("new"));
+
+        let (status, output) = run_cli(&[old.to_str().unwrap(), new.to_str().unwrap()]);
+
+        assert_eq!(status, 0);
+        assert_eq!(output, "0 files changed, 0 insertions(+), 0 deletions(-)\n");
+    }
+
+    #[test]
+    fn exits_1_for_differing_trees() {
+        let dir = tempfile::tempdir().unwrap();
+        write_tree(&dir.path().join("old"), &[("src/lib.rs", "fn a() {}\n")]);
+        write_tree(&dir.path().join("new"), &[("src/lib.rs", "fn b() {}\n")]);
+        let (old, new) = (dir.path().join("old"), dir.path().join("new"));
+
+        let (status, output) = run_cli(&[old.to_str().unwrap(), new.to_str().unwrap()]);
+
+        assert_eq!(status, 1);
+        assert!(output.starts_with("M  src/lib.rs  +1 -1\n"), "{output}");
+    }
+
+    #[test]
+    fn exits_2_for_an_unreadable_path() {
+        let dir = tempfile::tempdir().unwrap();
+        write_tree(dir.path(), &[("old/a.txt", "a\n")]);
+        let old = dir.path().join("old");
+        let missing = dir.path().join("missing");
+
+        let (status, output) = run_cli(&[old.to_str().unwrap(), missing.to_str().unwrap()]);
+
+        assert_eq!(status, 2);
+        assert_eq!(output, "");
+    }
+
+    #[test]
+    fn exits_2_for_bad_arguments() {
+        assert_eq!(run_cli(&["--frobnicate"]).0, 2);
+    }
+
+    #[test]
+    fn patches_a_too_large_change_with_a_marker() {
+        limits::set_limits(ExtractLimits {
+            max_file_bytes: 4,
+            ..Default::default()
+        });
+        let dir = tempfile::tempdir().unwrap();
+        let (old, new) = (dir.path().join("old.tar"), dir.path().join("new.tar"));
+        fs::write(&old, tar_of(&[("big", b"12345")])).unwrap();
+        fs::write(&new, tar_of(&[("big", b"54321")])).unwrap();
+
+        let (status, output) =
+            run_cli(&["--patch", old.to_str().unwrap(), new.to_str().unwrap()]);
+
+        assert_eq!(status, 1);
+        assert_eq!(
+            output,
+            "diff --git a/big b/big\n\
+             Binary files a/big and b/big differ (too large to include)\n"
+        );
+    }
+
+    #[test]
+    fn directories_are_loaded_within_the_extraction_limits() {
+        limits::set_limits(ExtractLimits {
+            max_file_bytes: 4,
+            max_entries: 3,
+            ..Default::default()
+        });
+        let dir = tempfile::tempdir().unwrap();
+        let (old, new) = (dir.path().join("old"), dir.path().join("new"));
+        write_tree(&old, &[("big", "12345"), ("src/small", "1")]);
+        write_tree(&new, &[("big", "54321"), ("src/small", "1")]);
+
+        let (status, output) =
+            run_cli(&["--patch", old.to_str().unwrap(), new.to_str().unwrap()]);
+        assert_eq!(status, 1);
+        assert_eq!(
+            output,
+            "diff --git a/big b/big\n\
+             Binary files a/big and b/big differ (too large to include)\n"
+        );
+
+        write_tree(&new, &[("src/another", "2")]);
+        let (status, output) =
+            run_cli(&["--patch", old.to_str().unwrap(), new.to_str().unwrap()]);
+        assert_eq!((status, output.as_str()), (2, ""));
+    }
+}
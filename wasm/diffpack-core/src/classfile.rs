@@ -0,0 +1,488 @@
+//! Summaries of compiled JVM `.class` files. Bytecode diffs are unreadable, but
+//! the class's declared shape (supertypes, fields and method signatures) is
+//! what API review cares about and survives recompilation unchanged.
+
+use crate::Error;
+
+const MAGIC: u32 = 0xCAFE_BABE;
+
+const ACC_PUBLIC: u16 = 0x0001;
+const ACC_PRIVATE: u16 = 0x0002;
+const ACC_PROTECTED: u16 = 0x0004;
+const ACC_STATIC: u16 = 0x0008;
+const ACC_FINAL: u16 = 0x0010;
+const ACC_SYNCHRONIZED: u16 = 0x0020;
+const ACC_VOLATILE: u16 = 0x0040;
+const ACC_TRANSIENT: u16 = 0x0080;
+const ACC_NATIVE: u16 = 0x0100;
+const ACC_INTERFACE: u16 = 0x0200;
+const ACC_ABSTRACT: u16 = 0x0400;
+const ACC_SYNTHETIC: u16 = 0x1000;
+const ACC_ANNOTATION: u16 = 0x2000;
+const ACC_ENUM: u16 = 0x4000;
+const ACC_MODULE: u16 = 0x8000;
+
+/// The JVM rejects descriptors with more array dimensions than this.
+const MAX_ARRAY_DIMENSIONS: usize = 255;
+
+enum Constant {
+    Utf8(String),
+    Class(u16),
+    /// Every other tag; only names and classes are needed for a summary.
+    Other,
+    /// Second slot of a `Long` or `Double`.
+    Unusable,
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        let slice = self
+            .pos
+            .checked_add(len)
+            .and_then(|end| self.bytes.get(self.pos..end))
+            .ok_or_else(|| corrupt("Truncated class file"))?;
+        self.pos += len;
+        Ok(slice)
+    }
+
+    fn u8(&mut self) -> Result<u8, Error> {
+        self.take(1).map(|b| b[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, Error> {
+        self.take(2).map(|b| u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, Error> {
+        self.take(4)
+            .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+}
+
+struct Member {
+    access: u16,
+    name: String,
+    descriptor: String,
+}
+
+/// Renders a class file as a Java-like declaration listing. Fails for anything
+/// that does not parse as a class file, so callers can fall back to treating it
+/// as an opaque binary.
+pub fn summarize_class(bytes: &[u8]) -> Result<String, Error> {
+    let mut reader = Reader { bytes, pos: 0 };
+    if reader.u32().ok() != Some(MAGIC) {
+        return Err(Error::UnsupportedFormat("Not a class file".to_string()));
+    }
+    let minor = reader.u16()?;
+    let major = reader.u16()?;
+
+    let pool = read_constant_pool(&mut reader)?;
+    let utf8 = |index: u16| match pool.get(index as usize) {
+        Some(Constant::Utf8(value)) => Ok(value.as_str()),
+        _ => Err(corrupt("Constant pool entry is not a string")),
+    };
+    let class_name = |index: u16| match pool.get(index as usize) {
+        Some(Constant::Class(name)) => utf8(*name).map(|name| name.replace('/', ".")),
+        _ => Err(corrupt("Constant pool entry is not a class")),
+    };
+
+    let access = reader.u16()?;
+    let this_class = class_name(reader.u16()?)?;
+    let super_index = reader.u16()?;
+    let super_class = if super_index == 0 {
+        None
+    } else {
+        Some(class_name(super_index)?)
+    };
+
+    let interface_count = reader.u16()?;
+    let mut interfaces = Vec::with_capacity(interface_count as usize);
+    for _ in 0..interface_count {
+        interfaces.push(class_name(reader.u16()?)?);
+    }
+
+    let fields = read_members(&mut reader, &utf8)?;
+    let methods = read_members(&mut reader, &utf8)?;
+
+    let mut out = String::new();
+    out.push_str(&format!("// class file version {major}.{minor}\n"));
+    out.push_str(&class_header(access, &this_class, super_class, &interfaces));
+    out.push_str(" {\n");
+
+    for field in &fields {
+        let modifiers = modifiers(
+            field.access,
+            &[
+                (ACC_PUBLIC, "public"),
+                (ACC_PRIVATE, "private"),
+                (ACC_PROTECTED, "protected"),
+                (ACC_STATIC, "static"),
+                (ACC_FINAL, "final"),
+                (ACC_VOLATILE, "volatile"),
+                (ACC_TRANSIENT, "transient"),
+            ],
+        );
+        let ty = parse_field_type(&mut field.descriptor.chars().peekable())?;
+        out.push_str(&format!("  {modifiers}{ty} {};\n", field.name));
+    }
+
+    if !fields.is_empty() && !methods.is_empty() {
+        out.push('\n');
+    }
+
+    let simple_name = this_class.rsplit('.').next().unwrap_or(&this_class);
+    for method in &methods {
+        // A static initializer is always `static`; saying so again would
+        // render `static static {}`.
+        if method.name == "<clinit>" {
+            out.push_str("  static {};\n");
+            continue;
+        }
+        let modifiers = modifiers(
+            method.access,
+            &[
+                (ACC_PUBLIC, "public"),
+                (ACC_PRIVATE, "private"),
+                (ACC_PROTECTED, "protected"),
+                (ACC_STATIC, "static"),
+                (ACC_FINAL, "final"),
+                (ACC_SYNCHRONIZED, "synchronized"),
+                (ACC_NATIVE, "native"),
+                (ACC_ABSTRACT, "abstract"),
+            ],
+        );
+        let (params, ret) = parse_method_descriptor(&method.descriptor)?;
+        let params = params.join(", ");
+        let signature = match method.name.as_str() {
+            "<init>" => format!("{simple_name}({params})"),
+            name => format!("{ret} {name}({params})"),
+        };
+        out.push_str(&format!("  {modifiers}{signature};\n"));
+    }
+
+    out.push_str("}\n");
+    Ok(out)
+}
+
+fn corrupt(message: &str) -> Error {
+    Error::Corrupt(message.to_string())
+}
+
+fn read_constant_pool(reader: &mut Reader<'_>) -> Result<Vec<Constant>, Error> {
+    let count = reader.u16()? as usize;
+    // Index 0 is unused; entries are 1-based.
+    let mut pool = Vec::with_capacity(count);
+    pool.push(Constant::Unusable);
+    while pool.len() < count {
+        let tag = reader.u8()?;
+        match tag {
+            1 => {
+                let len = reader.u16()? as usize;
+                let value = String::from_utf8_lossy(reader.take(len)?).into_owned();
+                pool.push(Constant::Utf8(value));
+            }
+            7 => pool.push(Constant::Class(reader.u16()?)),
+            3 | 4 => {
+                reader.take(4)?;
+                pool.push(Constant::Other);
+            }
+            5 | 6 => {
+                reader.take(8)?;
+                pool.push(Constant::Other);
+                pool.push(Constant::Unusable);
+            }
+            8 | 16 | 19 | 20 => {
+                reader.take(2)?;
+                pool.push(Constant::Other);
+            }
+            9 | 10 | 11 | 12 | 17 | 18 => {
+                reader.take(4)?;
+                pool.push(Constant::Other);
+            }
+            15 => {
+                reader.take(3)?;
+                pool.push(Constant::Other);
+            }
+            _ => return Err(corrupt(&format!("Unknown constant pool tag {tag}"))),
+        }
+    }
+    Ok(pool)
+}
+
+fn read_members<'p>(
+    reader: &mut Reader<'_>,
+    utf8: &impl Fn(u16) -> Result<&'p str, Error>,
+) -> Result<Vec<Member>, Error> {
+    let count = reader.u16()?;
+    let mut members = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let access = reader.u16()?;
+        let name = utf8(reader.u16()?)?.to_string();
+        let descriptor = utf8(reader.u16()?)?.to_string();
+        skip_attributes(reader)?;
+        // Compiler-generated members (bridges, lambdas, accessors) churn
+        // between builds without any source change.
+        if access & ACC_SYNTHETIC == 0 {
+            members.push(Member {
+                access,
+                name,
+                descriptor,
+            });
+        }
+    }
+    Ok(members)
+}
+
+fn skip_attributes(reader: &mut Reader<'_>) -> Result<(), Error> {
+    let count = reader.u16()?;
+    for _ in 0..count {
+        reader.u16()?;
+        let len = reader.u32()? as usize;
+        reader.take(len)?;
+    }
+    Ok(())
+}
+
+fn class_header(
+    access: u16,
+    name: &str,
+    super_class: Option<String>,
+    interfaces: &[String],
+) -> String {
+    let kind = if access & ACC_MODULE != 0 {
+        "module"
+    } else if access & ACC_ANNOTATION != 0 {
+        "@interface"
+    } else if access & ACC_INTERFACE != 0 {
+        "interface"
+    } else if access & ACC_ENUM != 0 {
+        "enum"
+    } else {
+        "class"
+    };
+
+    // Interfaces are implicitly abstract; saying so again is noise.
+    let mut flags = vec![(ACC_PUBLIC, "public"), (ACC_FINAL, "final")];
+    if access & ACC_INTERFACE == 0 {
+        flags.push((ACC_ABSTRACT, "abstract"));
+    }
+    let mut header = format!("{}{kind} {name}", modifiers(access, &flags));
+
+    let is_interface = access & ACC_INTERFACE != 0;
+    if let Some(super_class) = super_class {
+        if !is_interface && super_class != "java.lang.Object" && super_class != "java.lang.Enum" {
+            header.push_str(&format!(" extends {super_class}"));
+        }
+    }
+    if !interfaces.is_empty() {
+        let keyword = if is_interface {
+            "extends"
+        } else {
+            "implements"
+        };
+        header.push_str(&format!(" {keyword} {}", interfaces.join(", ")));
+    }
+    header
+}
+
+fn modifiers(access: u16, flags: &[(u16, &str)]) -> String {
+    flags
+        .iter()
+        .filter(|(flag, _)| access & flag != 0)
+        .map(|(_, name)| format!("{name} "))
+        .collect()
+}
+
+fn parse_method_descriptor(descriptor: &str) -> Result<(Vec<String>, String), Error> {
+    let malformed = || corrupt(&format!("Malformed method descriptor {descriptor}"));
+    let mut chars = descriptor.chars().peekable();
+    if chars.next() != Some('(') {
+        return Err(malformed());
+    }
+    let mut params = Vec::new();
+    while *chars.peek().ok_or_else(malformed)? != ')' {
+        params.push(parse_field_type(&mut chars)?);
+    }
+    chars.next();
+    let ret = if chars.peek() == Some(&'V') {
+        "void".to_string()
+    } else {
+        parse_field_type(&mut chars)?
+    };
+    Ok((params, ret))
+}
+
+/// Array dimensions are counted rather than recursed into, so a descriptor of
+/// a million `[`s cannot exhaust the stack.
+fn parse_field_type(chars: &mut std::iter::Peekable<std::str::Chars<'_>>) -> Result<String, Error> {
+    let mut dimensions = 0;
+    while chars.next_if_eq(&'[').is_some() {
+        dimensions += 1;
+        if dimensions > MAX_ARRAY_DIMENSIONS {
+            return Err(corrupt(&format!(
+                "Field descriptor has more than {MAX_ARRAY_DIMENSIONS} array dimensions"
+            )));
+        }
+    }
+    let mut ty = match chars.next() {
+        Some('B') => "byte".to_string(),
+        Some('C') => "char".to_string(),
+        Some('D') => "double".to_string(),
+        Some('F') => "float".to_string(),
+        Some('I') => "int".to_string(),
+        Some('J') => "long".to_string(),
+        Some('S') => "short".to_string(),
+        Some('Z') => "boolean".to_string(),
+        Some('L') => {
+            let name: String = chars.by_ref().take_while(|&ch| ch != ';').collect();
+            name.replace('/', ".")
+        }
+        _ => return Err(corrupt("Malformed field descriptor")),
+    };
+    ty.push_str(&"[]".repeat(dimensions));
+    Ok(ty)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn field_type(descriptor: &str) -> Result<String, Error> {
+        parse_field_type(&mut descriptor.chars().peekable())
+    }
+
+    #[test]
+    fn renders_array_dimensions_in_order() {
+        assert_eq!(field_type("I").unwrap(), "int");
+        assert_eq!(
+            field_type("[[Ljava/lang/String;").unwrap(),
+            "java.lang.String[][]"
+        );
+        assert_eq!(
+            parse_method_descriptor("([BI)[J").unwrap(),
+            (
+                vec!["byte[]".to_string(), "int".to_string()],
+                "long[]".to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn rejects_too_many_array_dimensions() {
+        let limit = format!("{}I", "[".repeat(MAX_ARRAY_DIMENSIONS));
+        assert!(field_type(&limit).is_ok());
+
+        let too_deep = format!("{}I", "[".repeat(MAX_ARRAY_DIMENSIONS + 1));
+        assert!(matches!(field_type(&too_deep), Err(Error::Corrupt(_))));
+    }
+
+    #[test]
+    fn hostile_descriptor_does_not_overflow_the_stack() {
+        let hostile = format!("({}I)V", "[".repeat(1_000_000));
+        assert!(matches!(
+            parse_method_descriptor(&hostile),
+            Err(Error::Corrupt(_))
+        ));
+    }
+
+    /// A class file with the given constant pool (UTF-8 strings, or class
+    /// references to another index) and members as `(access, name, descriptor)`
+    /// indices.
+    fn class_file(
+        pool: &[Result<&str, u16>],
+        this_class: u16,
+        super_class: u16,
+        fields: &[(u16, u16, u16)],
+        methods: &[(u16, u16, u16)],
+    ) -> Vec<u8> {
+        let mut class = MAGIC.to_be_bytes().to_vec();
+        class.extend([0, 0, 0, 52]);
+        class.extend((pool.len() as u16 + 1).to_be_bytes());
+        for constant in pool {
+            match constant {
+                Ok(text) => {
+                    class.push(1);
+                    class.extend((text.len() as u16).to_be_bytes());
+                    class.extend(text.as_bytes());
+                }
+                Err(name) => {
+                    class.push(7);
+                    class.extend(name.to_be_bytes());
+                }
+            }
+        }
+        class.extend((ACC_PUBLIC | 0x0020).to_be_bytes());
+        class.extend(this_class.to_be_bytes());
+        class.extend(super_class.to_be_bytes());
+        class.extend([0, 0]);
+        for members in [fields, methods] {
+            class.extend((members.len() as u16).to_be_bytes());
+            for (access, name, descriptor) in members {
+                class.extend(access.to_be_bytes());
+                class.extend(name.to_be_bytes());
+                class.extend(descriptor.to_be_bytes());
+                class.extend([0, 0]);
+            }
+        }
+        class.extend([0, 0]);
+        class
+    }
+
+    #[test]
+    fn summarizes_fields_constructors_methods_and_static_initializers() {
+        let class = class_file(
+            &[
+                Ok("com/example/Demo"),
+                Err(1),
+                Ok("java/lang/Object"),
+                Err(3),
+                Ok("COUNT"),
+                Ok("I"),
+                Ok("<init>"),
+                Ok("()V"),
+                Ok("greet"),
+                Ok("([Ljava/lang/String;)Ljava/lang/String;"),
+                Ok("<clinit>"),
+            ],
+            2,
+            4,
+            &[(ACC_PRIVATE | ACC_STATIC | ACC_FINAL, 5, 6)],
+            &[
+                (ACC_PUBLIC, 7, 8),
+                (ACC_PUBLIC, 9, 10),
+                (ACC_STATIC, 11, 8),
+            ],
+        );
+
+        assert_eq!(
+            summarize_class(&class).unwrap(),
+            concat!(
+                "// class file version 52.0\n",
+                "public class com.example.Demo {\n",
+                "  private static final int COUNT;\n",
+                "\n",
+                "  public Demo();\n",
+                "  public java.lang.String greet(java.lang.String[]);\n",
+                "  static {};\n",
+                "}\n",
+            )
+        );
+    }
+
+    #[test]
+    fn rejects_non_class_bytes() {
+        assert!(matches!(
+            summarize_class(b"PK\x03\x04"),
+            Err(Error::UnsupportedFormat(_))
+        ));
+        assert!(matches!(
+            summarize_class(&MAGIC.to_be_bytes()),
+            Err(Error::Corrupt(_))
+        ));
+    }
+}
@@ -0,0 +1,1100 @@
+use crate::types::{
+    AnomalyKind, ContentKind, DiffFileEntry, DiffHunk, DiffLine, DiffStatus, FileMapEntry,
+    FileType, InlineSpan, LineTag, PathAnomaly, SIGNIFICANT_MODE_BITS,
+};
+use similar::{ChangeTag, DiffOp, InlineChangeMode, InlineChangeOptions, TextDiff};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+
+/// Granularity of the intra-line highlighting attached to paired changed lines.
+#[derive(Debug, Clone, Copy)]
+pub enum InlineMode {
+    Word,
+    Char,
+}
+
+impl InlineMode {
+    pub fn parse(mode: &str) -> Option<Self> {
+        match mode {
+            "word" => Some(InlineMode::Word),
+            "char" => Some(InlineMode::Char),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HunkOptions {
+    /// Unchanged runs longer than this are collapsed and each group of changes
+    /// becomes its own hunk. `None` returns the whole file as a single hunk.
+    pub context_lines: Option<usize>,
+    /// Attach the spans that changed to deleted and inserted lines that replace
+    /// each other.
+    pub inline: Option<InlineMode>,
+}
+
+/// Line-level diff as typed hunks.
+pub fn get_diff_hunks(from_content: &str, to_content: &str, options: HunkOptions) -> Vec<DiffHunk> {
+    let diff = TextDiff::from_lines(from_content, to_content);
+    let groups = match options.context_lines {
+        Some(n) => diff.grouped_ops(n),
+        None => vec![diff.ops().to_vec()],
+    };
+
+    groups
+        .iter()
+        .filter(|ops| !ops.is_empty())
+        .map(|ops| build_hunk(&diff, ops, options.inline))
+        .collect()
+}
+
+fn build_hunk(
+    diff: &TextDiff<'_, '_, str>,
+    ops: &[DiffOp],
+    inline: Option<InlineMode>,
+) -> DiffHunk {
+    let first = &ops[0];
+    let last = &ops[ops.len() - 1];
+    let old_range = first.old_range().start..last.old_range().end;
+    let new_range = first.new_range().start..last.new_range().end;
+
+    let mut lines = Vec::new();
+    for op in ops {
+        match inline {
+            Some(mode) => push_inline_lines(&mut lines, diff, op, mode),
+            None => {
+                for change in diff.iter_changes(op) {
+                    lines.push(DiffLine {
+                        tag: line_tag(change.tag()),
+                        old_line: change.old_index().map(|index| index as u32 + 1),
+                        new_line: change.new_index().map(|index| index as u32 + 1),
+                        content: trim_line_ending(change.value()).to_string(),
+                        no_newline: !change.value().ends_with('\n'),
+                        inline: None,
+                    });
+                }
+            }
+        }
+    }
+
+    DiffHunk {
+        old_start: hunk_start(&old_range),
+        old_lines: old_range.len() as u32,
+        new_start: hunk_start(&new_range),
+        new_lines: new_range.len() as u32,
+        lines,
+    }
+}
+
+fn push_inline_lines(
+    lines: &mut Vec<DiffLine>,
+    diff: &TextDiff<'_, '_, str>,
+    op: &DiffOp,
+    mode: InlineMode,
+) {
+    let mut options = InlineChangeOptions::new();
+    options.mode(match mode {
+        InlineMode::Word => InlineChangeMode::Words,
+        InlineMode::Char => InlineChangeMode::Chars,
+    });
+
+    // No deadline: the default one reads the system clock, which panics on
+    // wasm32-unknown-unknown.
+    for change in diff.iter_inline_changes_with_options_deadline(op, options, None) {
+        let mut content = String::new();
+        let mut spans = Vec::new();
+        let mut offset = 0u32;
+        for (emphasized, value) in change.values() {
+            let start = offset;
+            offset += value.encode_utf16().count() as u32;
+            content.push_str(value);
+            if *emphasized && change.tag() != ChangeTag::Equal {
+                spans.push(InlineSpan { start, end: offset });
+            }
+        }
+
+        let no_newline = !content.ends_with('\n');
+        let trimmed_len = trim_line_ending(&content).len();
+        content.truncate(trimmed_len);
+        let content_len = content.encode_utf16().count() as u32;
+        spans.retain_mut(|span| {
+            span.end = span.end.min(content_len);
+            span.start < span.end
+        });
+
+        lines.push(DiffLine {
+            tag: line_tag(change.tag()),
+            old_line: change.old_index().map(|index| index as u32 + 1),
+            new_line: change.new_index().map(|index| index as u32 + 1),
+            content,
+            no_newline,
+            inline: (!spans.is_empty()).then_some(spans),
+        });
+    }
+}
+
+fn line_tag(tag: ChangeTag) -> LineTag {
+    match tag {
+        ChangeTag::Equal => LineTag::Equal,
+        ChangeTag::Insert => LineTag::Insert,
+        ChangeTag::Delete => LineTag::Delete,
+    }
+}
+
+fn hunk_start(range: &std::ops::Range<usize>) -> u32 {
+    if range.is_empty() {
+        range.start as u32
+    } else {
+        range.start as u32 + 1
+    }
+}
+
+fn trim_line_ending(line: &str) -> &str {
+    let line = line.strip_suffix('\n').unwrap_or(line);
+    line.strip_suffix('\r').unwrap_or(line)
+}
+
+/// Compares two extracted packages. It only borrows them, so callers that
+/// cache extractions can diff without copying.
+pub struct DiffTreeBuilder<'a> {
+    from_files: &'a HashMap<String, FileMapEntry>,
+    to_files: &'a HashMap<String, FileMapEntry>,
+    from_file_paths: HashSet<String>,
+    to_file_paths: HashSet<String>,
+    from_dirs: HashSet<String>,
+    to_dirs: HashSet<String>,
+    similarity_threshold: f64,
+}
+
+impl<'a> DiffTreeBuilder<'a> {
+    pub fn new(
+        from_files: &'a HashMap<String, FileMapEntry>,
+        to_files: &'a HashMap<String, FileMapEntry>,
+        similarity_threshold: f64,
+    ) -> Self {
+        let mut builder = Self {
+            from_files,
+            to_files,
+            from_file_paths: HashSet::new(),
+            to_file_paths: HashSet::new(),
+            from_dirs: HashSet::new(),
+            to_dirs: HashSet::new(),
+            similarity_threshold: similarity_threshold.clamp(0.0, 1.0),
+        };
+        builder.from_file_paths = builder.collect_file_paths(from_files);
+        builder.from_dirs = builder.collect_directories(from_files);
+        builder.to_file_paths = builder.collect_file_paths(to_files);
+        builder.to_dirs = builder.collect_directories(to_files);
+        builder
+    }
+
+    pub fn build_tree(&self) -> DiffFileEntry {
+        // 1. Identify added/removed files
+        let from_paths: HashSet<_> = self.from_files.keys().cloned().collect();
+        let to_paths: HashSet<_> = self.to_files.keys().cloned().collect();
+
+        let deleted: Vec<_> = self
+            .from_file_paths
+            .difference(&self.to_file_paths)
+            .cloned()
+            .collect();
+        let added: Vec<_> = self
+            .to_file_paths
+            .difference(&self.from_file_paths)
+            .cloned()
+            .collect();
+
+        // 2. Detect renames
+        let renames = self.detect_renames_optimized(&deleted, &added);
+
+        // 3. Build tree structure
+        let tree =
+            self.build_tree_structure(&from_paths, &to_paths, &self.from_dirs, &self.to_dirs);
+
+        // 4. Compute statuses and counts
+        self.compute_tree_stats(tree, &renames)
+    }
+
+    fn detect_renames_optimized(
+        &self,
+        deleted: &[String],
+        added: &[String],
+    ) -> HashMap<String, String> {
+        let mut renames = HashMap::new();
+        let mut used = HashSet::new();
+
+        // Phase 1: Exact content matches using hash-based lookup. This compares raw
+        // bytes, so binary files can be matched as renames here too.
+        let mut del_by_hash: HashMap<u64, Vec<&String>> = HashMap::new();
+        for del_path in deleted {
+            if let Some(entry) = self.file_entry(self.from_files, del_path) {
+                let hash = Self::hash_content(&entry.bytes);
+                del_by_hash.entry(hash).or_default().push(del_path);
+            }
+        }
+
+        for add_path in added {
+            if let Some(add_entry) = self.file_entry(self.to_files, add_path) {
+                let hash = Self::hash_content(&add_entry.bytes);
+
+                if let Some(candidates) = del_by_hash.get(&hash) {
+                    for del_path in candidates {
+                        if used.contains(*del_path) {
+                            continue;
+                        }
+
+                        if let Some(del_entry) = self.file_entry(self.from_files, del_path) {
+                            if add_entry.file_type == del_entry.file_type
+                                && add_entry.same_content(del_entry)
+                            {
+                                renames.insert(add_path.clone(), (*del_path).clone());
+                                used.insert((*del_path).clone());
+                                break;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        // Phase 2: Similar content with multi-stage filtering
+
+        // Pre-compute line sets for Jaccard similarity (fast pre-filter)
+        let mut del_line_sets: HashMap<&String, HashSet<&str>> = HashMap::new();
+        for del_path in deleted {
+            if used.contains(del_path) {
+                continue;
+            }
+            if let Some(content) = self.file_content(self.from_files, del_path) {
+                del_line_sets.insert(del_path, content.lines().collect());
+            }
+        }
+
+        for add_path in added {
+            if renames.contains_key(add_path) {
+                continue;
+            }
+
+            let add_content = match self.file_content(self.to_files, add_path) {
+                Some(c) => c,
+                None => continue,
+            };
+
+            let add_lines: HashSet<&str> = add_content.lines().collect();
+            let add_name = add_path.rsplit('/').next().unwrap_or("");
+            let mut best: Option<(String, f64)> = None;
+
+            for del_path in deleted {
+                if used.contains(del_path) {
+                    continue;
+                }
+
+                let del_content = match self.file_content(self.from_files, del_path) {
+                    Some(c) => c,
+                    None => continue,
+                };
+
+                // Filter 1: Length ratio check (very fast)
+                if !self.can_be_similar(del_content, add_content) {
+                    continue;
+                }
+
+                // Filter 2: Jaccard similarity on line sets (fast)
+                let del_lines = del_line_sets.get(del_path).unwrap();
+                let jaccard = self.jaccard_similarity(&add_lines, del_lines);
+
+                // Early reject if Jaccard is too low (threshold * 0.7 as heuristic)
+                if jaccard < self.similarity_threshold * 0.7 {
+                    continue;
+                }
+
+                // Filter 3: Expensive diff-based similarity (only for promising candidates)
+                let similarity = self.calculate_similarity(del_content, add_content);
+
+                // Filename boost
+                let del_name = del_path.rsplit('/').next().unwrap_or("");
+                let adjusted = if add_name == del_name {
+                    similarity * 1.2
+                } else {
+                    similarity
+                };
+
+                if adjusted >= self.similarity_threshold {
+                    if let Some((_, best_sim)) = &best {
+                        if adjusted > *best_sim {
+                            best = Some((del_path.clone(), adjusted));
+                        }
+                    } else {
+                        best = Some((del_path.clone(), adjusted));
+                    }
+                }
+            }
+
+            if let Some((from_path, _)) = best {
+                renames.insert(add_path.clone(), from_path.clone());
+                used.insert(from_path);
+            }
+        }
+
+        renames
+    }
+
+    fn jaccard_similarity(&self, set1: &HashSet<&str>, set2: &HashSet<&str>) -> f64 {
+        if set1.is_empty() && set2.is_empty() {
+            return 1.0;
+        }
+
+        let intersection = set1.intersection(set2).count();
+        let union = set1.len() + set2.len() - intersection;
+
+        if union == 0 {
+            return 0.0;
+        }
+
+        intersection as f64 / union as f64
+    }
+
+    fn can_be_similar(&self, from: &str, to: &str) -> bool {
+        let len_ratio = from.len() as f64 / to.len().max(1) as f64;
+        len_ratio >= self.similarity_threshold && len_ratio <= 1.0 / self.similarity_threshold
+    }
+
+    fn hash_content(content: &[u8]) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        content.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    fn calculate_similarity(&self, from: &str, to: &str) -> f64 {
+        if from == to {
+            return 1.0;
+        }
+        if from.is_empty() || to.is_empty() {
+            return 0.0;
+        }
+
+        let diff = TextDiff::from_lines(from, to);
+
+        // Count changes using the 'similar' crate
+        let mut added = 0;
+        let mut removed = 0;
+        let mut unchanged = 0;
+
+        for change in diff.iter_all_changes() {
+            match change.tag() {
+                ChangeTag::Insert => added += 1,
+                ChangeTag::Delete => removed += 1,
+                ChangeTag::Equal => unchanged += 1,
+            }
+        }
+
+        let total = (added + removed + unchanged).max(1);
+        unchanged as f64 / total as f64
+    }
+
+    fn build_tree_structure(
+        &self,
+        from_paths: &HashSet<String>,
+        to_paths: &HashSet<String>,
+        from_dirs: &HashSet<String>,
+        to_dirs: &HashSet<String>,
+    ) -> DiffFileEntry {
+        // Merge all paths
+        let mut all_paths = HashSet::new();
+        all_paths.extend(from_paths.iter().cloned());
+        all_paths.extend(to_paths.iter().cloned());
+        all_paths.extend(from_dirs.iter().cloned());
+        all_paths.extend(to_dirs.iter().cloned());
+
+        let mut nodes: HashMap<String, DiffFileEntry> = HashMap::new();
+        let mut children_map: HashMap<String, Vec<String>> = HashMap::new();
+
+        for path in &all_paths {
+            if path == "/" {
+                continue;
+            }
+            let file_type = self.resolve_file_type(path);
+
+            nodes.insert(
+                path.clone(),
+                DiffFileEntry {
+                    path: path.clone(),
+                    old_path: None,
+                    file_type,
+                    status: DiffStatus::Unchanged,
+                    binary: false,
+                    manifest: false,
+                    too_large: false,
+                    old_mode: None,
+                    new_mode: None,
+                    link_target: None,
+                    old_link_target: None,
+                    anomalies: self.collect_anomalies(path),
+                    added: None,
+                    removed: None,
+                    children: Some(Vec::new()),
+                },
+            );
+
+            let parent = Self::parent_path(path);
+            children_map.entry(parent).or_default().push(path.clone());
+        }
+
+        let mut root = DiffFileEntry {
+            path: "/".to_string(),
+            old_path: None,
+            file_type: FileType::Directory,
+            status: DiffStatus::Unchanged,
+            binary: false,
+            manifest: false,
+            too_large: false,
+            old_mode: None,
+            new_mode: None,
+            link_target: None,
+            old_link_target: None,
+            anomalies: Vec::new(),
+            added: None,
+            removed: None,
+            children: Some(Vec::new()),
+        };
+
+        root.children = Some(Self::build_children("/", &mut nodes, &mut children_map));
+        root
+    }
+
+    /// Anomalies recorded for `path` in either version, without repeats, plus
+    /// symlinks that point outside the package.
+    fn collect_anomalies(&self, path: &str) -> Vec<PathAnomaly> {
+        let mut anomalies: Vec<PathAnomaly> = Vec::new();
+        for entry in [self.from_files.get(path), self.to_files.get(path)]
+            .into_iter()
+            .flatten()
+        {
+            let escape = entry
+                .link_target
+                .as_ref()
+                .filter(|target| entry.file_type == FileType::Symlink && link_escapes(path, target))
+                .map(|target| PathAnomaly {
+                    kind: AnomalyKind::LinkEscape,
+                    raw_path: target.clone(),
+                });
+            for anomaly in entry.anomalies.iter().chain(&escape) {
+                if !anomalies.contains(anomaly) {
+                    anomalies.push(anomaly.clone());
+                }
+            }
+        }
+        anomalies
+    }
+
+    fn collect_directories(&self, entries: &HashMap<String, FileMapEntry>) -> HashSet<String> {
+        let mut dirs = HashSet::new();
+
+        for (path, entry) in entries {
+            // Add directory entries
+            if matches!(entry.file_type, FileType::Directory) {
+                dirs.insert(path.clone());
+            }
+
+            // Add parent directories
+            if let Some(last_slash) = path.rfind('/') {
+                let mut end = last_slash;
+                while end > 0 {
+                    if let Some(slash_pos) = path[..end].rfind('/') {
+                        dirs.insert(path[..end].to_string());
+                        end = slash_pos;
+                    } else {
+                        // Add the first component if not root
+                        if end > 0 {
+                            dirs.insert(path[..end].to_string());
+                        }
+                        break;
+                    }
+                }
+            }
+        }
+
+        dirs
+    }
+
+    fn build_children(
+        parent: &str,
+        nodes: &mut HashMap<String, DiffFileEntry>,
+        children_map: &mut HashMap<String, Vec<String>>,
+    ) -> Vec<DiffFileEntry> {
+        let mut child_paths = match children_map.remove(parent) {
+            Some(paths) => paths,
+            None => return Vec::new(),
+        };
+
+        child_paths.sort();
+        let mut children = Vec::with_capacity(child_paths.len());
+
+        for child_path in child_paths {
+            let mut node = match nodes.remove(&child_path) {
+                Some(entry) => entry,
+                None => continue,
+            };
+
+            let nested = Self::build_children(&child_path, nodes, children_map);
+            node.children = Some(nested);
+            children.push(node);
+        }
+
+        children
+    }
+
+    fn parent_path(path: &str) -> String {
+        if let Some(last_slash) = path.rfind('/') {
+            if last_slash == 0 {
+                "/".to_string()
+            } else {
+                path[..last_slash].to_string()
+            }
+        } else {
+            "/".to_string()
+        }
+    }
+
+    fn compute_tree_stats(
+        &self,
+        mut root: DiffFileEntry,
+        renames: &HashMap<String, String>,
+    ) -> DiffFileEntry {
+        self.compute_node_stats(&mut root, renames, &self.from_dirs, &self.to_dirs);
+        root
+    }
+
+    fn compute_node_stats(
+        &self,
+        node: &mut DiffFileEntry,
+        renames: &HashMap<String, String>,
+        from_dirs: &HashSet<String>,
+        to_dirs: &HashSet<String>,
+    ) -> (u32, u32) {
+        match node.file_type {
+            FileType::File | FileType::Symlink => {
+                let counts = self.compute_file_stats(node, renames);
+                self.compare_links_and_modes(node);
+                counts
+            }
+            FileType::Directory => {
+                // Recursively compute stats for children
+                let mut total_added = 0;
+                let mut total_removed = 0;
+                let mut all_unchanged = true;
+
+                if let Some(ref mut children) = node.children {
+                    for child in children.iter_mut() {
+                        let (added, removed) =
+                            self.compute_node_stats(child, renames, from_dirs, to_dirs);
+                        total_added += added;
+                        total_removed += removed;
+
+                        if !matches!(child.status, DiffStatus::Unchanged) {
+                            all_unchanged = false;
+                        }
+                    }
+                }
+
+                node.added = Some(total_added);
+                node.removed = Some(total_removed);
+
+                // Determine directory status
+                let in_from = node.path == "/" || from_dirs.contains(&node.path);
+                let in_to = node.path == "/" || to_dirs.contains(&node.path);
+
+                if !in_from && in_to {
+                    node.status = DiffStatus::Added;
+                } else if in_from && !in_to {
+                    node.status = DiffStatus::Removed;
+                } else if all_unchanged {
+                    node.status = DiffStatus::Unchanged;
+                } else {
+                    node.status = DiffStatus::Modified;
+                }
+
+                (total_added, total_removed)
+            }
+        }
+    }
+
+    fn compute_file_stats(
+        &self,
+        node: &mut DiffFileEntry,
+        renames: &HashMap<String, String>,
+    ) -> (u32, u32) {
+        // Check if this file is a rename
+        let from_path = match renames.get(&node.path) {
+            Some(old_path) => {
+                node.status = DiffStatus::Renamed;
+                node.old_path = Some(old_path.clone());
+                old_path.as_str()
+            }
+            None => node.path.as_str(),
+        };
+
+        let from_entry = self.file_entry(self.from_files, from_path);
+        let to_entry = self.file_entry(self.to_files, &node.path);
+
+        // Binaries and undecodable files have no meaningful lines, so they
+        // only ever report whether their bytes changed.
+        let is_binary = [from_entry, to_entry]
+            .iter()
+            .flatten()
+            .any(|entry| entry.text().is_none());
+        if is_binary {
+            node.binary = true;
+            node.too_large = [from_entry, to_entry]
+                .iter()
+                .flatten()
+                .any(|entry| matches!(entry.kind, ContentKind::TooLarge { .. }));
+            node.status = match (from_entry, to_entry) {
+                _ if renames.contains_key(&node.path) => DiffStatus::Renamed,
+                (Some(from), Some(to)) if from.same_content(to) => DiffStatus::Unchanged,
+                (Some(_), Some(_)) => DiffStatus::Modified,
+                (Some(_), None) => DiffStatus::Removed,
+                (None, Some(_)) => DiffStatus::Added,
+                (None, None) => DiffStatus::Unchanged,
+            };
+            return (0, 0);
+        }
+
+        let from_content = from_entry.and_then(FileMapEntry::text);
+        let to_content = to_entry.and_then(FileMapEntry::text);
+
+        if renames.contains_key(&node.path) {
+            if let (Some(from), Some(to)) = (from_content, to_content) {
+                let (added, removed) = self.count_diff(from, to);
+                node.added = Some(added);
+                node.removed = Some(removed);
+                return (added, removed);
+            }
+        }
+
+        match (from_content, to_content) {
+            (Some(from), Some(to)) => {
+                if from == to {
+                    node.status = DiffStatus::Unchanged;
+                    node.added = Some(0);
+                    node.removed = Some(0);
+                    (0, 0)
+                } else {
+                    node.status = DiffStatus::Modified;
+                    let (added, removed) = self.count_diff(from, to);
+                    node.added = Some(added);
+                    node.removed = Some(removed);
+                    (added, removed)
+                }
+            }
+            (Some(from), None) => {
+                node.status = DiffStatus::Removed;
+                let removed = from.lines().count() as u32;
+                node.added = Some(0);
+                node.removed = Some(removed);
+                (0, removed)
+            }
+            (None, Some(to)) => {
+                node.status = DiffStatus::Added;
+                let added = to.lines().count() as u32;
+                node.added = Some(added);
+                node.removed = Some(0);
+                (added, 0)
+            }
+            (None, None) => {
+                node.status = DiffStatus::Unchanged;
+                node.added = Some(0);
+                node.removed = Some(0);
+                (0, 0)
+            }
+        }
+    }
+
+    /// Records link targets and significant mode changes, and refines the
+    /// status for entries whose content alone did not change: a symlink with a
+    /// new target, or a file that became or stopped being a hard link to
+    /// another with the same content, is `LinkChanged`; a file that became a
+    /// symlink (or the other way round) is `Modified`; and an unchanged file
+    /// whose execute or special bits changed is `ModeChanged`.
+    fn compare_links_and_modes(&self, node: &mut DiffFileEntry) {
+        let from_path = node.old_path.as_deref().unwrap_or(&node.path);
+        let from_entry = self.file_entry(self.from_files, from_path);
+        let to_entry = self.file_entry(self.to_files, &node.path);
+        if let Some(entry) = to_entry.or(from_entry) {
+            node.file_type = entry.file_type.clone();
+            node.link_target = entry.link_target.clone();
+        }
+
+        let (Some(from), Some(to)) = (from_entry, to_entry) else {
+            return;
+        };
+        if from.file_type != to.file_type {
+            if matches!(node.status, DiffStatus::Unchanged) {
+                node.status = DiffStatus::Modified;
+            }
+            return;
+        }
+        if from.link_target != to.link_target {
+            node.old_link_target = from.link_target.clone();
+            // A symlink's content is its target, so a new target already shows
+            // as modified; a hard link's content is the file it copies.
+            let only_link_changed = if to.file_type == FileType::Symlink {
+                matches!(node.status, DiffStatus::Modified)
+            } else {
+                matches!(node.status, DiffStatus::Unchanged)
+            };
+            if only_link_changed {
+                node.status = DiffStatus::LinkChanged;
+            }
+        }
+        if to.file_type == FileType::Symlink {
+            return;
+        }
+        if let (Some(old_mode), Some(new_mode)) = (from.mode, to.mode) {
+            if (old_mode ^ new_mode) & SIGNIFICANT_MODE_BITS != 0 {
+                node.old_mode = Some(old_mode);
+                node.new_mode = Some(new_mode);
+                if matches!(node.status, DiffStatus::Unchanged) {
+                    node.status = DiffStatus::ModeChanged;
+                }
+            }
+        }
+    }
+
+    fn count_diff(&self, from: &str, to: &str) -> (u32, u32) {
+        let diff = TextDiff::from_lines(from, to);
+
+        let mut added = 0;
+        let mut removed = 0;
+
+        for change in diff.iter_all_changes() {
+            match change.tag() {
+                ChangeTag::Insert => added += 1,
+                ChangeTag::Delete => removed += 1,
+                _ => {}
+            }
+        }
+
+        (added, removed)
+    }
+
+    fn collect_file_paths(&self, entries: &HashMap<String, FileMapEntry>) -> HashSet<String> {
+        entries
+            .iter()
+            .filter_map(|(path, entry)| {
+                if matches!(entry.file_type, FileType::File | FileType::Symlink) {
+                    Some(path.clone())
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
+    fn resolve_file_type(&self, path: &str) -> FileType {
+        if let Some(entry) = self
+            .from_files
+            .get(path)
+            .or_else(|| self.to_files.get(path))
+        {
+            return entry.file_type.clone();
+        }
+
+        FileType::Directory
+    }
+
+    fn file_entry(
+        &self,
+        entries: &'a HashMap<String, FileMapEntry>,
+        path: &str,
+    ) -> Option<&'a FileMapEntry> {
+        entries
+            .get(path)
+            .filter(|entry| matches!(entry.file_type, FileType::File | FileType::Symlink))
+    }
+
+    /// Text of a regular file. Symlinks are left out so a link target is never
+    /// paired with a file's content as a similar rename.
+    fn file_content(
+        &self,
+        entries: &'a HashMap<String, FileMapEntry>,
+        path: &str,
+    ) -> Option<&'a str> {
+        self.file_entry(entries, path)
+            .filter(|entry| entry.file_type == FileType::File)
+            .and_then(FileMapEntry::text)
+    }
+}
+
+/// Whether a symlink at `path` (relative to the package root) resolves outside
+/// the package: an absolute target, or one whose `..` climb above the root.
+fn link_escapes(path: &str, target: &str) -> bool {
+    let target = target.replace('\\', "/");
+    let bytes = target.as_bytes();
+    if target.starts_with('/')
+        || (bytes.len() >= 2 && bytes[0].is_ascii_alphabetic() && bytes[1] == b':')
+    {
+        return true;
+    }
+
+    let mut depth = path.matches('/').count();
+    for component in target.split('/') {
+        match component {
+            "" | "." => {}
+            ".." if depth == 0 => return true,
+            ".." => depth -= 1,
+            _ => depth += 1,
+        }
+    }
+    false
+}
+
+pub fn build_diff_tree(
+    from_files: &HashMap<String, FileMapEntry>,
+    to_files: &HashMap<String, FileMapEntry>,
+    similarity_threshold: f64,
+) -> DiffFileEntry {
+    DiffTreeBuilder::new(from_files, to_files, similarity_threshold).build_tree()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn find<'t>(node: &'t DiffFileEntry, path: &str) -> Option<&'t DiffFileEntry> {
+        if node.path == path {
+            return Some(node);
+        }
+        node.children
+            .iter()
+            .flatten()
+            .find_map(|child| find(child, path))
+    }
+
+    #[test]
+    fn too_large_digests_do_not_match_files_holding_the_same_bytes() {
+        let digest: Vec<u8> = (0..32).collect();
+        let too_large = FileMapEntry::too_large(5 << 20, digest.clone());
+        let from = HashMap::from([
+            ("big.bin".to_string(), too_large.clone()),
+            ("old.bin".to_string(), too_large),
+        ]);
+        let to = HashMap::from([
+            ("big.bin".to_string(), FileMapEntry::file(digest.clone())),
+            ("new.bin".to_string(), FileMapEntry::file(digest)),
+        ]);
+
+        let tree = build_diff_tree(&from, &to, 0.75);
+
+        assert!(matches!(find(&tree, "big.bin").unwrap().status, DiffStatus::Modified));
+        let added = find(&tree, "new.bin").unwrap();
+        assert!(matches!(added.status, DiffStatus::Added));
+        assert_eq!(added.old_path, None);
+        assert!(matches!(find(&tree, "old.bin").unwrap().status, DiffStatus::Removed));
+    }
+
+    fn ranges(hunks: &[DiffHunk]) -> Vec<(u32, u32, u32, u32)> {
+        hunks
+            .iter()
+            .map(|hunk| {
+                (
+                    hunk.old_start,
+                    hunk.old_lines,
+                    hunk.new_start,
+                    hunk.new_lines,
+                )
+            })
+            .collect()
+    }
+
+    fn numbered(lines: std::ops::RangeInclusive<u32>, replace: &[(u32, &str)]) -> String {
+        lines
+            .map(|n| {
+                let line = replace
+                    .iter()
+                    .find(|(at, _)| *at == n)
+                    .map_or(n.to_string(), |(_, text)| text.to_string());
+                format!("{line}\n")
+            })
+            .collect()
+    }
+
+    #[test]
+    fn splits_hunks_at_long_unchanged_runs() {
+        let from = numbered(1..=10, &[]);
+        let to = numbered(1..=10, &[(2, "two"), (9, "nine")]);
+
+        let hunks = get_diff_hunks(
+            &from,
+            &to,
+            HunkOptions {
+                context_lines: Some(1),
+                inline: None,
+            },
+        );
+
+        assert_eq!(ranges(&hunks), [(1, 3, 1, 3), (8, 3, 8, 3)]);
+        let first: Vec<_> = hunks[0]
+            .lines
+            .iter()
+            .map(|line| {
+                (
+                    line.tag,
+                    line.old_line,
+                    line.new_line,
+                    line.content.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            first,
+            [
+                (LineTag::Equal, Some(1), Some(1), "1"),
+                (LineTag::Delete, Some(2), None, "2"),
+                (LineTag::Insert, None, Some(2), "two"),
+                (LineTag::Equal, Some(3), Some(3), "3"),
+            ]
+        );
+    }
+
+    #[test]
+    fn returns_the_whole_file_without_context_lines() {
+        let from = numbered(1..=10, &[]);
+        let to = numbered(1..=10, &[(5, "five")]);
+
+        let hunks = get_diff_hunks(&from, &to, HunkOptions::default());
+
+        assert_eq!(ranges(&hunks), [(1, 10, 1, 10)]);
+        assert_eq!(hunks[0].lines.len(), 11);
+        assert!(get_diff_hunks(&from, &from, HunkOptions::default())
+            .iter()
+            .all(|hunk| hunk.lines.iter().all(|line| line.tag == LineTag::Equal)));
+    }
+
+    #[test]
+    fn empty_ranges_start_before_the_change() {
+        let added = get_diff_hunks(
+            "",
+            "a\nb\n",
+            HunkOptions {
+                context_lines: Some(3),
+                inline: None,
+            },
+        );
+        assert_eq!(ranges(&added), [(0, 0, 1, 2)]);
+
+        let removed = get_diff_hunks(
+            "a\nb\n",
+            "a\n",
+            HunkOptions {
+                context_lines: Some(0),
+                inline: None,
+            },
+        );
+        assert_eq!(ranges(&removed), [(2, 1, 1, 0)]);
+    }
+
+    #[test]
+    fn flags_last_lines_without_a_newline() {
+        let hunks = get_diff_hunks(
+            "a\nb",
+            "a\nb\n",
+            HunkOptions {
+                context_lines: Some(3),
+                inline: None,
+            },
+        );
+
+        let lines: Vec<_> = hunks[0]
+            .lines
+            .iter()
+            .map(|line| (line.tag, line.content.as_str(), line.no_newline))
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                (LineTag::Equal, "a", false),
+                (LineTag::Delete, "b", true),
+                (LineTag::Insert, "b", false),
+            ]
+        );
+    }
+
+    #[test]
+    fn marks_changed_words_within_replaced_lines() {
+        let hunks = get_diff_hunks(
+            "let x = 1;\n",
+            "let x = 2;\n",
+            HunkOptions {
+                context_lines: Some(3),
+                inline: Some(InlineMode::Word),
+            },
+        );
+
+        let spans: Vec<_> = hunks[0]
+            .lines
+            .iter()
+            .map(|line| {
+                line.inline
+                    .iter()
+                    .flatten()
+                    .map(|span| (span.start, span.end))
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        // Words run between whitespace, so the `;` goes with the digit.
+        assert_eq!(spans, [vec![(8, 10)], vec![(8, 10)]]);
+
+        // Offsets count UTF-16 code units, as JS strings do.
+        let hunks = get_diff_hunks(
+            "\u{1F600}a\n",
+            "\u{1F600}b\n",
+            HunkOptions {
+                context_lines: Some(3),
+                inline: Some(InlineMode::Char),
+            },
+        );
+        let inserted = hunks[0].lines[1].inline.as_ref().unwrap();
+        assert_eq!((inserted[0].start, inserted[0].end), (2, 3));
+    }
+
+    fn hard_link(target: &str, bytes: &[u8]) -> FileMapEntry {
+        let mut entry = FileMapEntry::file(bytes.to_vec());
+        entry.link_target = Some(target.to_string());
+        entry
+    }
+
+    #[test]
+    fn reports_hard_link_target_changes() {
+        let from = HashMap::from([
+            ("a".to_string(), FileMapEntry::file(b"same\n".to_vec())),
+            ("b".to_string(), FileMapEntry::file(b"same\n".to_vec())),
+            ("link".to_string(), hard_link("a", b"same\n")),
+            ("copy".to_string(), hard_link("a", b"same\n")),
+        ]);
+        let to = HashMap::from([
+            ("a".to_string(), FileMapEntry::file(b"same\n".to_vec())),
+            ("b".to_string(), FileMapEntry::file(b"same\n".to_vec())),
+            ("link".to_string(), hard_link("b", b"same\n")),
+            ("copy".to_string(), FileMapEntry::file(b"same\n".to_vec())),
+        ]);
+
+        let tree = build_diff_tree(&from, &to, 0.75);
+
+        let link = find(&tree, "link").unwrap();
+        assert!(matches!(link.status, DiffStatus::LinkChanged));
+        assert_eq!(link.old_link_target.as_deref(), Some("a"));
+        assert_eq!(link.link_target.as_deref(), Some("b"));
+
+        let copy = find(&tree, "copy").unwrap();
+        assert!(matches!(copy.status, DiffStatus::LinkChanged));
+        assert_eq!(copy.old_link_target.as_deref(), Some("a"));
+        assert_eq!(copy.link_target, None);
+
+        assert!(matches!(
+            find(&tree, "a").unwrap().status,
+            DiffStatus::Unchanged
+        ));
+    }
+}
@@ -0,0 +1,170 @@
+//! Go's `h1:` module hash, as computed by `golang.org/x/mod/sumdb/dirhash`
+//! and recorded in `go.sum` and the checksum database.
+
+use std::io::Cursor;
+
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use sha2::{Digest, Sha256};
+use zip::ZipArchive;
+
+use crate::limits::Budget;
+use crate::Error;
+
+/// `dirhash.HashZip` with `Hash1`: every entry name of the module zip, sorted,
+/// is listed as `<sha256 hex>  <name>\n`, and the SHA-256 of that listing is
+/// base64-encoded behind an `h1:` prefix. Names are hashed as stored in the zip,
+/// including the `<module>@<version>/` prefix.
+pub fn hash_module_zip(bytes: &[u8]) -> Result<String, Error> {
+    let mut budget = Budget::new(bytes.len())?;
+    let mut archive = ZipArchive::new(Cursor::new(bytes))
+        .map_err(|err| Error::Corrupt(format!("Zip parsing failed: {err}")))?;
+
+    let mut lines = Vec::with_capacity(archive.len());
+    for i in 0..archive.len() {
+        budget.count_entry()?;
+        let entry = archive
+            .by_index(i)
+            .map_err(|err| Error::Corrupt(format!("Zip entry error: {err}")))?;
+        let name = entry.name().to_string();
+        if name.contains('\n') {
+            return Err(Error::Corrupt(format!(
+                "Module zip entry name contains a newline: {name:?}"
+            )));
+        }
+
+        let mut hasher = Sha256::new();
+        budget.stream(entry, &mut hasher, "Zip read")?;
+        lines.push((name, hex(&hasher.finalize())));
+    }
+    lines.sort_by(|a, b| a.0.cmp(&b.0));
+    Ok(hash1(&lines))
+}
+
+/// The hash `go.sum` records on `<module> <version>/go.mod` lines: `Hash1`
+/// over a single file named `go.mod`.
+pub fn hash_go_mod(contents: &[u8]) -> String {
+    hash1(&[("go.mod".to_string(), hex(&Sha256::digest(contents)))])
+}
+
+fn hash1(sorted_lines: &[(String, String)]) -> String {
+    let mut summary = Sha256::new();
+    for (name, digest) in sorted_lines {
+        summary.update(format!("{digest}  {name}\n"));
+    }
+    format!("h1:{}", BASE64.encode(summary.finalize()))
+}
+
+/// One `go.sum` line: `<module> <version>[/go.mod] h1:<hash>`.
+pub struct GoSumLine<'a> {
+    pub module: &'a str,
+    pub version: &'a str,
+    /// Whether the line covers only the go.mod file rather than the module zip.
+    pub go_mod: bool,
+    pub hash: &'a str,
+}
+
+impl<'a> GoSumLine<'a> {
+    pub fn parse(line: &'a str) -> Option<Self> {
+        let mut fields = line.split_whitespace();
+        let (module, version, hash) = (fields.next()?, fields.next()?, fields.next()?);
+        if fields.next().is_some() || !hash.starts_with("h1:") {
+            return None;
+        }
+        let (version, go_mod) = match version.strip_suffix("/go.mod") {
+            Some(version) => (version, true),
+            None => (version, false),
+        };
+        Some(Self {
+            module,
+            version,
+            go_mod,
+            hash,
+        })
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::archive::test_support::zip_of;
+
+    /// `golang.org/x/text v0.3.0/go.mod`, as recorded in go.sum files. That
+    /// go.mod is the single line below.
+    const X_TEXT_GO_MOD: &[u8] = b"module golang.org/x/text\n";
+    const X_TEXT_GO_MOD_HASH: &str = "h1:NqM8EUOU14njkJ3fqMW+pc6Ldnwhi/IjpwHt7yyuwOQ=";
+
+    #[test]
+    fn hashes_go_mod_like_go_sum() {
+        assert_eq!(hash_go_mod(X_TEXT_GO_MOD), X_TEXT_GO_MOD_HASH);
+        assert_eq!(
+            hash_go_mod(b"module github.com/pmezard/go-difflib\n"),
+            "h1:iKH77koFhYxTK1pcRnkKkqfTogsbg7gZNVY4sRDYZ/4="
+        );
+    }
+
+    #[test]
+    fn hashes_a_module_zip_by_its_entries() {
+        // A zip holding only a file named go.mod has the same listing as the
+        // go.mod hash, so the known hash applies to it too.
+        let zip = zip_of(&[("go.mod", X_TEXT_GO_MOD)]);
+        assert_eq!(hash_module_zip(&zip).unwrap(), X_TEXT_GO_MOD_HASH);
+
+        // Entry order in the zip does not matter; names are sorted.
+        let ab = zip_of(&[("m@v1.0.0/a.go", b"a"), ("m@v1.0.0/b.go", b"b")]);
+        let ba = zip_of(&[("m@v1.0.0/b.go", b"b"), ("m@v1.0.0/a.go", b"a")]);
+        assert_eq!(hash_module_zip(&ab).unwrap(), hash_module_zip(&ba).unwrap());
+        assert_ne!(hash_module_zip(&ab).unwrap(), X_TEXT_GO_MOD_HASH);
+    }
+
+    #[test]
+    fn rejects_entry_names_with_newlines() {
+        let zip = zip_of(&[("m@v1.0.0/a\n.go", b"a")]);
+        assert!(matches!(hash_module_zip(&zip), Err(Error::Corrupt(_))));
+    }
+
+    #[test]
+    fn parses_go_sum_lines() {
+        let zip = GoSumLine::parse(
+            "github.com/davecgh/go-spew v1.1.0 h1:ZDRjVQ15GmhC3fiQ8ni8+OwkZQO4DARzQgrnXU1Liz8=",
+        )
+        .unwrap();
+        assert_eq!(
+            (zip.module, zip.version, zip.go_mod),
+            ("github.com/davecgh/go-spew", "v1.1.0", false)
+        );
+
+        let go_mod = GoSumLine::parse(
+            "github.com/davecgh/go-spew v1.1.0/go.mod h1:J7Y8YcW2NihsgmVo/mv3lAwl/skON4iLHjSsI+c5H38=",
+        )
+        .unwrap();
+        assert_eq!(
+            (go_mod.module, go_mod.version, go_mod.go_mod),
+            ("github.com/davecgh/go-spew", "v1.1.0", true)
+        );
+        assert_eq!(
+            go_mod.hash,
+            "h1:J7Y8YcW2NihsgmVo/mv3lAwl/skON4iLHjSsI+c5H38="
+        );
+        assert_eq!(
+            hash_go_mod(b"module github.com/davecgh/go-spew\n"),
+            go_mod.hash
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_go_sum_lines() {
+        for line in [
+            "",
+            "example.com/m v1.0.0",
+            "example.com/m v1.0.0 sha256:abc",
+            "example.com/m v1.0.0 h1:abc extra",
+        ] {
+            assert!(GoSumLine::parse(line).is_none(), "{line:?}");
+        }
+    }
+}
@@ -0,0 +1,28 @@
+use std::fmt;
+
+/// Failures while reading an archive. The wasm bindings turn these into
+/// `JsValue`s at the boundary; native callers get a regular error type.
+#[derive(Debug)]
+pub enum Error {
+    /// The bytes are not in any archive format diffpack can read.
+    UnsupportedFormat(String),
+    /// The bytes looked like a gzip, tar or zip stream but could not be read.
+    Corrupt(String),
+    /// A nested archive format (gems, Hex tarballs) lacks a required member.
+    MissingMember(String),
+    /// Extraction stopped at one of the [`ExtractLimits`](crate::archive::ExtractLimits).
+    LimitExceeded(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::UnsupportedFormat(message)
+            | Error::Corrupt(message)
+            | Error::LimitExceeded(message) => f.write_str(message),
+            Error::MissingMember(path) => write!(f, "Archive is missing {path}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
@@ -0,0 +1,14 @@
+//! Archive extraction and diffing for diffpack, with no browser or
+//! wasm-bindgen dependencies. The `diff-wasm` crate wraps this for the web app
+//! and the `diffpack` binary wraps it for the command line.
+
+pub mod archive;
+pub mod classfile;
+pub mod diff;
+pub mod dirhash;
+mod error;
+pub mod limits;
+pub mod patch;
+pub mod types;
+
+pub use error::Error;
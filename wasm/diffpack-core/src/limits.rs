@@ -0,0 +1,402 @@
+//! Caps that keep a hostile archive from exhausting the memory of whatever is
+//! extracting it. A small `.tgz` can expand to gigabytes, so every limit is
+//! enforced while decompressing rather than checked afterwards.
+
+use std::cell::Cell;
+use std::io::{self, Read, Write};
+
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use crate::types::FileMapEntry;
+use crate::Error;
+
+/// Highly compressible archives are normal at small sizes (a 2 KB tarball of
+/// source can easily be 50x), so the ratio only applies past this many
+/// decompressed bytes.
+const RATIO_GRACE_BYTES: u64 = 64 << 20;
+
+/// Missing fields keep their defaults, so callers can override a single limit.
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct ExtractLimits {
+    /// Largest archive accepted, before any decompression.
+    pub max_compressed_bytes: u64,
+    /// Most bytes an archive may decompress to, every entry included.
+    pub max_total_bytes: u64,
+    /// Files larger than this are not kept. They are hashed as they stream past
+    /// and extracted as [`FileMapEntry::too_large`] instead of failing the
+    /// extraction.
+    pub max_file_bytes: u64,
+    /// Most entries, directories included, an archive may list.
+    pub max_entries: usize,
+    /// Most an archive may expand relative to its compressed size.
+    pub max_ratio: u64,
+}
+
+impl Default for ExtractLimits {
+    fn default() -> Self {
+        Self {
+            max_compressed_bytes: 256 << 20,
+            max_total_bytes: 512 << 20,
+            max_file_bytes: 64 << 20,
+            max_entries: 200_000,
+            max_ratio: 100,
+        }
+    }
+}
+
+thread_local! {
+    static LIMITS: Cell<ExtractLimits> = Cell::new(ExtractLimits::default());
+}
+
+/// Limits every later extraction on this thread uses.
+pub fn set_limits(limits: ExtractLimits) {
+    LIMITS.with(|current| current.set(limits));
+}
+
+pub fn limits() -> ExtractLimits {
+    LIMITS.with(Cell::get)
+}
+
+/// What one extraction may still decompress. Created once per downloaded
+/// archive, so nested layers share its allowance: a tar inside gzip, and an
+/// archive inside another through [`Extraction`](crate::archive::Extraction).
+pub(crate) struct Budget {
+    limits: ExtractLimits,
+    /// The lower of the total-size cap and the ratio cap.
+    cap: u64,
+    used: u64,
+    entries: usize,
+}
+
+impl Budget {
+    pub(crate) fn new(compressed_len: usize) -> Result<Self, Error> {
+        let limits = limits();
+        let compressed_len = compressed_len as u64;
+        if compressed_len > limits.max_compressed_bytes {
+            return Err(Error::LimitExceeded(format!(
+                "Archive is {compressed_len} bytes, over the {}-byte limit",
+                limits.max_compressed_bytes
+            )));
+        }
+        let ratio_cap = compressed_len
+            .saturating_mul(limits.max_ratio)
+            .max(RATIO_GRACE_BYTES);
+        Ok(Self {
+            limits,
+            cap: ratio_cap.min(limits.max_total_bytes),
+            used: 0,
+            entries: 0,
+        })
+    }
+
+    pub(crate) fn exceeded(&self) -> Error {
+        if self.cap == self.limits.max_total_bytes {
+            Error::LimitExceeded(format!(
+                "Archive expands past the {}-byte limit",
+                self.limits.max_total_bytes
+            ))
+        } else {
+            Error::LimitExceeded(format!(
+                "Archive expands more than {}x, which looks like a decompression bomb",
+                self.limits.max_ratio
+            ))
+        }
+    }
+
+    fn remaining(&self) -> u64 {
+        self.cap - self.used
+    }
+
+    pub(crate) fn count_entry(&mut self) -> Result<(), Error> {
+        self.entries += 1;
+        if self.entries > self.limits.max_entries {
+            return Err(Error::LimitExceeded(format!(
+                "Archive has more than {} entries",
+                self.limits.max_entries
+            )));
+        }
+        Ok(())
+    }
+
+    /// Caps a decompressed stream that is parsed again as an archive, such as
+    /// the tar inside a gzip. The stream may be as large as the entire
+    /// allowance, but does not spend it: its entries do when they are read.
+    /// Reading past the cap fails and sets `overrun`, so the caller can report
+    /// the limit rather than whatever parse error it caused.
+    pub(crate) fn cap_stream<'a, R: Read>(
+        &self,
+        reader: R,
+        overrun: &'a Cell<bool>,
+    ) -> CappedReader<'a, R> {
+        CappedReader {
+            inner: reader,
+            remaining: self.cap,
+            overrun,
+        }
+    }
+
+    /// Decompresses a whole stream into memory, for callers that need the
+    /// bytes rather than the entries.
+    pub(crate) fn decompress(&self, reader: impl Read, context: &str) -> Result<Vec<u8>, Error> {
+        let overrun = Cell::new(false);
+        let mut decompressed = Vec::new();
+        let result = self
+            .cap_stream(reader, &overrun)
+            .read_to_end(&mut decompressed);
+        if overrun.get() {
+            return Err(self.exceeded());
+        }
+        result.map_err(|err| Error::Corrupt(format!("{context} failed: {err}")))?;
+        Ok(decompressed)
+    }
+
+    /// Spends `len` bytes that were decompressed in one piece.
+    pub(crate) fn spend(&mut self, len: u64) -> Result<(), Error> {
+        if len > self.remaining() {
+            return Err(self.exceeded());
+        }
+        self.used += len;
+        Ok(())
+    }
+
+    /// Reads one file entry, keeping it only if it is within the per-file limit.
+    pub(crate) fn read_file(
+        &mut self,
+        mut reader: impl Read,
+        context: &str,
+    ) -> Result<FileMapEntry, Error> {
+        let keep = self.limits.max_file_bytes.min(self.remaining());
+        let mut contents = Vec::new();
+        (&mut reader)
+            .take(keep + 1)
+            .read_to_end(&mut contents)
+            .map_err(|err| Error::Corrupt(format!("{context} failed: {err}")))?;
+        let len = contents.len() as u64;
+        if len > self.remaining() {
+            return Err(self.exceeded());
+        }
+        self.used += len;
+        if len <= keep {
+            return Ok(FileMapEntry::file(contents));
+        }
+
+        let mut hasher = Sha256::new();
+        hasher.update(&contents);
+        drop(contents);
+        let rest = self.stream(reader, &mut hasher, context)?;
+        Ok(FileMapEntry::too_large(
+            len + rest,
+            hasher.finalize().to_vec(),
+        ))
+    }
+
+    /// Copies `reader` into `writer` without buffering it, spending the
+    /// allowance as it goes.
+    pub(crate) fn stream(
+        &mut self,
+        reader: impl Read,
+        writer: &mut impl Write,
+        context: &str,
+    ) -> Result<u64, Error> {
+        let copied = io::copy(&mut reader.take(self.remaining() + 1), writer)
+            .map_err(|err| Error::Corrupt(format!("{context} failed: {err}")))?;
+        if copied > self.remaining() {
+            return Err(self.exceeded());
+        }
+        self.used += copied;
+        Ok(copied)
+    }
+}
+
+/// The total, per-file and entry limits for files that are already unpacked,
+/// such as a directory on disk. With no compressed size to compare against,
+/// the compressed and ratio limits do not apply.
+pub struct UnpackedBudget(Budget);
+
+impl UnpackedBudget {
+    pub fn new() -> Self {
+        let limits = limits();
+        Self(Budget {
+            limits,
+            cap: limits.max_total_bytes,
+            used: 0,
+            entries: 0,
+        })
+    }
+
+    pub fn count_entry(&mut self) -> Result<(), Error> {
+        self.0.count_entry()
+    }
+
+    /// Reads one file, keeping it only if it is within the per-file limit.
+    pub fn read_file(&mut self, reader: impl Read, context: &str) -> Result<FileMapEntry, Error> {
+        self.0.read_file(reader, context)
+    }
+}
+
+impl Default for UnpackedBudget {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// See [`Budget::cap_stream`].
+pub(crate) struct CappedReader<'a, R> {
+    inner: R,
+    remaining: u64,
+    overrun: &'a Cell<bool>,
+}
+
+impl<R: Read> Read for CappedReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // One byte past the cap tells a stream that ends right at it from one
+        // that goes on.
+        let max = usize::try_from(self.remaining.saturating_add(1)).unwrap_or(usize::MAX);
+        let len = buf.len().min(max);
+        let read = self.inner.read(&mut buf[..len])? as u64;
+        if read > self.remaining {
+            self.overrun.set(true);
+            return Err(io::Error::other(
+                "decompressed stream is over the size limit",
+            ));
+        }
+        self.remaining -= read;
+        Ok(read as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::ContentKind;
+
+    fn new_budget(limits: ExtractLimits, compressed_len: usize) -> Result<Budget, Error> {
+        set_limits(limits);
+        Budget::new(compressed_len)
+    }
+
+    fn message(err: Error) -> String {
+        match err {
+            Error::LimitExceeded(message) => message,
+            err => panic!("expected a limit error, got {err}"),
+        }
+    }
+
+    #[test]
+    fn rejects_archives_over_the_compressed_limit() {
+        let limits = ExtractLimits {
+            max_compressed_bytes: 10,
+            ..Default::default()
+        };
+        assert!(new_budget(limits, 10).is_ok());
+        let err = new_budget(limits, 11).err().unwrap();
+        assert_eq!(message(err), "Archive is 11 bytes, over the 10-byte limit");
+    }
+
+    #[test]
+    fn caps_the_total_at_the_lower_of_size_and_ratio() {
+        let total = ExtractLimits {
+            max_total_bytes: 8,
+            ..Default::default()
+        };
+        let mut budget = new_budget(total, 1).unwrap();
+        assert_eq!(
+            budget.read_file(&b"12345678"[..], "read").unwrap().bytes,
+            b"12345678"
+        );
+        let err = budget.read_file(&b"9"[..], "read").unwrap_err();
+        assert_eq!(message(err), "Archive expands past the 8-byte limit");
+
+        // Past the grace allowance, the ratio is the lower cap.
+        let ratio = ExtractLimits {
+            max_ratio: 2,
+            ..Default::default()
+        };
+        let mut budget = new_budget(ratio, (RATIO_GRACE_BYTES / 2 + 1) as usize).unwrap();
+        let mut sink = io::sink();
+        let within = RATIO_GRACE_BYTES + 2;
+        budget
+            .stream(io::repeat(0).take(within), &mut sink, "read")
+            .unwrap();
+        let err = budget.stream(&b"x"[..], &mut sink, "read").unwrap_err();
+        assert!(message(err).contains("more than 2x"));
+    }
+
+    #[test]
+    fn keeps_files_up_to_the_per_file_limit() {
+        let limits = ExtractLimits {
+            max_file_bytes: 4,
+            ..Default::default()
+        };
+        let mut budget = new_budget(limits, 1).unwrap();
+
+        let kept = budget.read_file(&b"1234"[..], "read").unwrap();
+        assert_eq!(kept.bytes, b"1234");
+
+        let large = budget.read_file(&b"123456"[..], "read").unwrap();
+        assert!(matches!(large.kind, ContentKind::TooLarge { size: 6 }));
+        assert_eq!(large.bytes, Sha256::digest(b"123456").to_vec());
+        assert_eq!(budget.used, 10);
+    }
+
+    #[test]
+    fn counts_entries() {
+        let limits = ExtractLimits {
+            max_entries: 2,
+            ..Default::default()
+        };
+        let mut budget = new_budget(limits, 1).unwrap();
+        budget.count_entry().unwrap();
+        budget.count_entry().unwrap();
+        let err = budget.count_entry().unwrap_err();
+        assert_eq!(message(err), "Archive has more than 2 entries");
+    }
+
+    #[test]
+    fn unpacked_files_skip_the_ratio_but_not_the_total() {
+        set_limits(ExtractLimits {
+            max_total_bytes: 12,
+            max_file_bytes: 4,
+            max_ratio: 1,
+            ..Default::default()
+        });
+        let mut budget = UnpackedBudget::new();
+
+        assert_eq!(budget.read_file(&b"1234"[..], "read").unwrap().bytes, b"1234");
+        let large = budget.read_file(&b"12345"[..], "read").unwrap();
+        assert!(matches!(large.kind, ContentKind::TooLarge { size: 5 }));
+        let err = budget.read_file(&b"1234"[..], "read").unwrap_err();
+        assert_eq!(message(err), "Archive expands past the 12-byte limit");
+    }
+
+    #[test]
+    fn capped_streams_fail_past_the_cap_without_spending_it() {
+        let limits = ExtractLimits {
+            max_total_bytes: 4,
+            ..Default::default()
+        };
+        let budget = new_budget(limits, 1).unwrap();
+
+        let overrun = Cell::new(false);
+        let mut read = Vec::new();
+        budget
+            .cap_stream(&b"1234"[..], &overrun)
+            .read_to_end(&mut read)
+            .unwrap();
+        assert_eq!((read.as_slice(), overrun.get()), (&b"1234"[..], false));
+        assert_eq!(budget.used, 0);
+
+        let mut read = Vec::new();
+        assert!(budget
+            .cap_stream(&b"12345"[..], &overrun)
+            .read_to_end(&mut read)
+            .is_err());
+        assert!(overrun.get());
+
+        let err = budget.decompress(&b"12345"[..], "read").unwrap_err();
+        assert_eq!(message(err), "Archive expands past the 4-byte limit");
+        assert_eq!(budget.decompress(&b"1234"[..], "read").unwrap(), b"1234");
+    }
+}
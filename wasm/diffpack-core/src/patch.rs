@@ -0,0 +1,532 @@
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+use std::io::Write as _;
+
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use sha1::{Digest, Sha1};
+
+use crate::diff::{get_diff_hunks, HunkOptions};
+use crate::types::{ContentKind, DiffFileEntry, DiffStatus, FileMapEntry, FileType, LineTag};
+
+pub const DEFAULT_CONTEXT_LINES: usize = 3;
+
+#[derive(Clone, Copy, Debug)]
+pub struct PatchOptions {
+    /// Unchanged lines kept around each change.
+    pub context_lines: usize,
+    /// Write binary files as `GIT binary patch` literals, which only `git apply`
+    /// accepts, instead of `Binary files ... differ` markers.
+    pub binary_literals: bool,
+    /// Diff files in an unknown encoding as lossily decoded text rather than
+    /// as binaries. For display only: the result no longer applies.
+    pub lossy_text: bool,
+}
+
+impl Default for PatchOptions {
+    fn default() -> Self {
+        Self {
+            context_lines: DEFAULT_CONTEXT_LINES,
+            binary_literals: false,
+            lossy_text: false,
+        }
+    }
+}
+
+/// Renders every changed file of a diff tree as one git-style unified diff that
+/// `git apply` and `patch -p1` accept. Renames get `rename from`/`rename to`
+/// headers and every changed file gets a full `index` line. Files without a
+/// line representation get a `Binary files a/<path> and b/<path> differ` marker,
+/// or a `GIT binary patch` literal with `binary_literals`. Files over the
+/// extraction size limit get the same marker noting they were too large to
+/// include, since only their hash is known. Symlinks are written the way git
+/// writes them, as mode `120000` files whose content is the target, and
+/// execute-bit changes get `old mode`/`new mode` headers.
+pub fn build_patch(
+    tree: &DiffFileEntry,
+    from_files: &HashMap<String, FileMapEntry>,
+    to_files: &HashMap<String, FileMapEntry>,
+    options: PatchOptions,
+) -> String {
+    // The tree still lists a rename's source as a removed file; the rename entry
+    // already covers it.
+    let mut rename_sources = HashSet::new();
+    collect_rename_sources(tree, &mut rename_sources);
+
+    let mut patch = String::new();
+    let files = PatchFiles {
+        from_files,
+        to_files,
+        rename_sources: &rename_sources,
+        options,
+    };
+    write_node(&mut patch, tree, &files);
+    patch
+}
+
+/// One file's section of [`build_patch`], for showing a single change. `from`
+/// and `to` are the file at `old_path` and `new_path`; either may be missing.
+pub fn build_file_patch(
+    old_path: &str,
+    new_path: &str,
+    from: Option<&FileMapEntry>,
+    to: Option<&FileMapEntry>,
+    options: PatchOptions,
+) -> String {
+    let mut patch = String::new();
+    write_file_patch(&mut patch, old_path, new_path, from, to, options);
+    patch
+}
+
+struct PatchFiles<'a> {
+    from_files: &'a HashMap<String, FileMapEntry>,
+    to_files: &'a HashMap<String, FileMapEntry>,
+    rename_sources: &'a HashSet<&'a str>,
+    options: PatchOptions,
+}
+
+/// Old paths of every renamed file. The tree also lists each of them as a
+/// removed file, which consumers of the whole tree usually want to skip.
+pub fn collect_rename_sources<'a>(node: &'a DiffFileEntry, sources: &mut HashSet<&'a str>) {
+    if let Some(old_path) = &node.old_path {
+        sources.insert(old_path);
+    }
+    for child in node.children.iter().flatten() {
+        collect_rename_sources(child, sources);
+    }
+}
+
+fn write_node(patch: &mut String, node: &DiffFileEntry, files: &PatchFiles<'_>) {
+    match node.file_type {
+        FileType::Directory => {
+            for child in node.children.iter().flatten() {
+                write_node(patch, child, files);
+            }
+        }
+        FileType::File | FileType::Symlink => {
+            match node.status {
+                DiffStatus::Unchanged => return,
+                DiffStatus::Removed if files.rename_sources.contains(node.path.as_str()) => return,
+                _ => {}
+            }
+            let old_path = node.old_path.as_deref().unwrap_or(&node.path);
+            let from = files
+                .from_files
+                .get(old_path)
+                .filter(|entry| is_file(entry));
+            let to = files
+                .to_files
+                .get(&node.path)
+                .filter(|entry| is_file(entry));
+            write_file_patch(patch, old_path, &node.path, from, to, files.options);
+        }
+    }
+}
+
+fn write_file_patch(
+    patch: &mut String,
+    old_path: &str,
+    new_path: &str,
+    from: Option<&FileMapEntry>,
+    to: Option<&FileMapEntry>,
+    options: PatchOptions,
+) {
+    if let (Some(from_entry), Some(to_entry)) = (from, to) {
+        // git cannot express a type change in place; it deletes and re-adds.
+        if from_entry.file_type != to_entry.file_type {
+            write_file_patch(patch, old_path, old_path, from, None, options);
+            write_file_patch(patch, new_path, new_path, None, to, options);
+            return;
+        }
+        let mode_changed = git_mode(from_entry) != git_mode(to_entry);
+        if !mode_changed && old_path == new_path && from_entry.same_content(to_entry) {
+            return;
+        }
+    }
+
+    let old_name = from.map_or("/dev/null".to_string(), |_| format!("a/{old_path}"));
+    let new_name = to.map_or("/dev/null".to_string(), |_| format!("b/{new_path}"));
+
+    let _ = writeln!(patch, "diff --git a/{old_path} b/{new_path}");
+
+    match (from.map(git_mode), to.map(git_mode)) {
+        (None, Some(mode)) => {
+            let _ = writeln!(patch, "new file mode {mode}");
+        }
+        (Some(mode), None) => {
+            let _ = writeln!(patch, "deleted file mode {mode}");
+        }
+        (Some(old_mode), Some(new_mode)) if old_mode != new_mode => {
+            let _ = writeln!(patch, "old mode {old_mode}");
+            let _ = writeln!(patch, "new mode {new_mode}");
+        }
+        _ => {}
+    }
+    if old_path != new_path {
+        let _ = writeln!(patch, "rename from {old_path}");
+        let _ = writeln!(patch, "rename to {new_path}");
+    }
+
+    if let (Some(from_entry), Some(to_entry)) = (from, to) {
+        if from_entry.same_content(to_entry) {
+            return;
+        }
+    }
+
+    // Only the hash of a file over the size limit is known, so there is nothing
+    // to write but a note that it changed.
+    let too_large = |entry: Option<&FileMapEntry>| {
+        entry.is_some_and(|entry| matches!(entry.kind, ContentKind::TooLarge { .. }))
+    };
+    if too_large(from) || too_large(to) {
+        let _ = writeln!(
+            patch,
+            "Binary files {old_name} and {new_name} differ (too large to include)"
+        );
+        return;
+    }
+
+    let from_bytes = from.map_or(&[][..], |entry| &entry.bytes);
+    let to_bytes = to.map_or(&[][..], |entry| &entry.bytes);
+
+    // git apply needs the full object ids to apply a binary patch.
+    let old_id = from.map_or(NULL_OBJECT_ID.to_string(), |_| blob_id(from_bytes));
+    let new_id = to.map_or(NULL_OBJECT_ID.to_string(), |_| blob_id(to_bytes));
+    match (from, to) {
+        (Some(from), Some(to)) if git_mode(from) == git_mode(to) => {
+            let _ = writeln!(patch, "index {old_id}..{new_id} {}", git_mode(to));
+        }
+        _ => {
+            let _ = writeln!(patch, "index {old_id}..{new_id}");
+        }
+    }
+
+    let from_text = from.map(|entry| patch_text(entry, options));
+    let to_text = to.map(|entry| patch_text(entry, options));
+    match (from_text, to_text) {
+        (Some(None), _) | (_, Some(None)) if options.binary_literals => {
+            patch.push_str("GIT binary patch\n");
+            write_binary_literal(patch, to_bytes);
+            write_binary_literal(patch, from_bytes);
+        }
+        (Some(None), _) | (_, Some(None)) => {
+            let _ = writeln!(patch, "Binary files {old_name} and {new_name} differ");
+        }
+        (from_text, to_text) => {
+            let from_text = from_text.flatten().unwrap_or_default();
+            let to_text = to_text.flatten().unwrap_or_default();
+            let _ = writeln!(patch, "--- {old_name}\n+++ {new_name}");
+            write_hunks(patch, &from_text, &to_text, options.context_lines);
+        }
+    }
+}
+
+fn patch_text(entry: &FileMapEntry, options: PatchOptions) -> Option<Cow<'_, str>> {
+    if options.lossy_text {
+        entry.display_text()
+    } else {
+        entry.text().map(Cow::Borrowed)
+    }
+}
+
+const NULL_OBJECT_ID: &str = "0000000000000000000000000000000000000000";
+
+/// The id git gives `bytes` as a blob.
+fn blob_id(bytes: &[u8]) -> String {
+    let mut hasher = Sha1::new();
+    hasher.update(format!("blob {}\0", bytes.len()));
+    hasher.update(bytes);
+    hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
+
+/// One `literal` hunk of a git binary patch: the whole content, deflated and
+/// base85-encoded in lines of up to 52 bytes, each prefixed with its length.
+fn write_binary_literal(patch: &mut String, bytes: &[u8]) {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::best());
+    let deflated = encoder
+        .write_all(bytes)
+        .and_then(|()| encoder.finish())
+        .expect("deflating into memory cannot fail");
+
+    let _ = writeln!(patch, "literal {}", bytes.len());
+    for line in deflated.chunks(52) {
+        let len = line.len() as u8;
+        patch.push(if len <= 26 {
+            (b'A' + len - 1) as char
+        } else {
+            (b'a' + len - 27) as char
+        });
+        for group in line.chunks(4) {
+            let mut word = [0u8; 4];
+            word[..group.len()].copy_from_slice(group);
+            let mut value = u32::from_be_bytes(word);
+            let mut encoded = [0u8; 5];
+            for digit in encoded.iter_mut().rev() {
+                *digit = BASE85_ALPHABET[(value % 85) as usize];
+                value /= 85;
+            }
+            patch.extend(encoded.iter().map(|&digit| digit as char));
+        }
+        patch.push('\n');
+    }
+    patch.push('\n');
+}
+
+const BASE85_ALPHABET: &[u8; 85] =
+    b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz!#$%&()*+-;<=>?@^_`{|}~";
+
+/// Writes the hunks of a text diff. Lines are copied from the source texts
+/// rather than from the hunks, which drop `\r` along with the line ending.
+fn write_hunks(patch: &mut String, from_text: &str, to_text: &str, context_lines: usize) {
+    let old_lines: Vec<&str> = from_text.split_inclusive('\n').collect();
+    let new_lines: Vec<&str> = to_text.split_inclusive('\n').collect();
+    let options = HunkOptions {
+        context_lines: Some(context_lines),
+        inline: None,
+    };
+
+    for hunk in get_diff_hunks(from_text, to_text, options) {
+        let _ = writeln!(
+            patch,
+            "@@ -{} +{} @@",
+            hunk_range(hunk.old_start, hunk.old_lines),
+            hunk_range(hunk.new_start, hunk.new_lines)
+        );
+        for line in hunk.lines {
+            let (sign, raw) = match line.tag {
+                LineTag::Equal => (' ', line.old_line.map(|n| old_lines[n as usize - 1])),
+                LineTag::Delete => ('-', line.old_line.map(|n| old_lines[n as usize - 1])),
+                LineTag::Insert => ('+', line.new_line.map(|n| new_lines[n as usize - 1])),
+            };
+            let raw = raw.unwrap_or_default();
+            patch.push(sign);
+            patch.push_str(raw.strip_suffix('\n').unwrap_or(raw));
+            patch.push('\n');
+            if line.no_newline {
+                patch.push_str("\\ No newline at end of file\n");
+            }
+        }
+    }
+}
+
+/// `start,len` as in a unified diff hunk header, where a length of one is
+/// left out.
+fn hunk_range(start: u32, len: u32) -> String {
+    if len == 1 {
+        start.to_string()
+    } else {
+        format!("{start},{len}")
+    }
+}
+
+fn is_file(entry: &FileMapEntry) -> bool {
+    matches!(entry.file_type, FileType::File | FileType::Symlink)
+}
+
+/// The only modes git records: symlink, executable and regular file.
+fn git_mode(entry: &FileMapEntry) -> &'static str {
+    match (&entry.file_type, entry.mode) {
+        (FileType::Symlink, _) => "120000",
+        (_, Some(mode)) if mode & 0o111 != 0 => "100755",
+        _ => "100644",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process::Command;
+
+    use super::*;
+    use crate::diff::build_diff_tree;
+
+    fn files(entries: &[(&str, &[u8])]) -> HashMap<String, FileMapEntry> {
+        entries
+            .iter()
+            .map(|(path, bytes)| (path.to_string(), FileMapEntry::file(bytes.to_vec())))
+            .collect()
+    }
+
+    #[test]
+    fn writes_a_modified_file_as_a_unified_diff() {
+        let from = files(&[("a.txt", b"a\nkeep\n")]);
+        let to = files(&[("a.txt", b"b\nkeep\n")]);
+        let tree = build_diff_tree(&from, &to, 0.75);
+
+        assert_eq!(
+            build_patch(&tree, &from, &to, PatchOptions::default()),
+            "diff --git a/a.txt b/a.txt\n\
+             index 4b98c59378dfafdad8d56e06cecc36008a093aeb..e669bb6562553b4fcb33f59d265a03d297aa682f 100644\n\
+             --- a/a.txt\n\
+             +++ b/a.txt\n\
+             @@ -1,2 +1,2 @@\n\
+             -a\n\
+             +b\n \
+             keep\n"
+        );
+    }
+
+    #[test]
+    fn writes_added_removed_and_renamed_files() {
+        let from = files(&[("gone.txt", b"x\n"), ("old.txt", b"one\ntwo\nthree\n")]);
+        let to = files(&[("new.txt", b"one\ntwo\nthree\n"), ("added.txt", b"y")]);
+        let tree = build_diff_tree(&from, &to, 0.75);
+        let patch = build_patch(&tree, &from, &to, PatchOptions::default());
+
+        // A rename's source is not also written as a deletion.
+        assert_eq!(
+            patch,
+            "diff --git a/added.txt b/added.txt\n\
+             new file mode 100644\n\
+             index 0000000000000000000000000000000000000000..e25f1814e51579d5f55c0f1fe0135ddb28a47f4a\n\
+             --- /dev/null\n\
+             +++ b/added.txt\n\
+             @@ -0,0 +1 @@\n\
+             +y\n\
+             \\ No newline at end of file\n\
+             diff --git a/gone.txt b/gone.txt\n\
+             deleted file mode 100644\n\
+             index 587be6b4c3f93f93c489c0111bba5596147a26cb..0000000000000000000000000000000000000000\n\
+             --- a/gone.txt\n\
+             +++ /dev/null\n\
+             @@ -1 +0,0 @@\n\
+             -x\n\
+             diff --git a/old.txt b/new.txt\n\
+             rename from old.txt\n\
+             rename to new.txt\n"
+        );
+    }
+
+    #[test]
+    fn marks_too_large_files_and_leaves_out_unchanged_ones() {
+        let mut from = files(&[("same.txt", b"same\n")]);
+        let mut to = files(&[("same.txt", b"same\n")]);
+        from.insert("big".to_string(), FileMapEntry::too_large(10, vec![1; 32]));
+        to.insert("big".to_string(), FileMapEntry::too_large(11, vec![2; 32]));
+        let tree = build_diff_tree(&from, &to, 0.75);
+
+        assert_eq!(
+            build_patch(&tree, &from, &to, PatchOptions::default()),
+            "diff --git a/big b/big\n\
+             Binary files a/big and b/big differ (too large to include)\n"
+        );
+    }
+
+    #[test]
+    fn writes_headers_before_the_too_large_marker() {
+        let big = FileMapEntry::too_large(10, vec![1; 32]);
+        let bigger = FileMapEntry::too_large(11, vec![2; 32]);
+
+        assert_eq!(
+            build_file_patch("big", "big", None, Some(&big), PatchOptions::default()),
+            "diff --git a/big b/big\n\
+             new file mode 100644\n\
+             Binary files /dev/null and b/big differ (too large to include)\n"
+        );
+        assert_eq!(
+            build_file_patch("big", "big", Some(&big), None, PatchOptions::default()),
+            "diff --git a/big b/big\n\
+             deleted file mode 100644\n\
+             Binary files a/big and /dev/null differ (too large to include)\n"
+        );
+        assert_eq!(
+            build_file_patch("old", "new", Some(&big), Some(&bigger), PatchOptions::default()),
+            "diff --git a/old b/new\n\
+             rename from old\n\
+             rename to new\n\
+             Binary files a/old and b/new differ (too large to include)\n"
+        );
+        assert_eq!(
+            build_file_patch("old", "new", Some(&big), Some(&big), PatchOptions::default()),
+            "diff --git a/old b/new\nrename from old\nrename to new\n"
+        );
+    }
+
+    #[test]
+    fn diffs_unknown_encodings_as_text_only_when_lossy() {
+        let from = FileMapEntry::file(b"caf\xe9\nkeep\n".to_vec());
+        let to = FileMapEntry::file(b"the\xe9\nkeep\n".to_vec());
+        let patch = |lossy_text| {
+            let options = PatchOptions {
+                lossy_text,
+                ..PatchOptions::default()
+            };
+            build_file_patch("menu.txt", "menu.txt", Some(&from), Some(&to), options)
+        };
+
+        assert!(patch(false).ends_with("Binary files a/menu.txt and b/menu.txt differ\n"));
+        assert!(patch(true).ends_with("@@ -1,2 +1,2 @@\n-caf\u{fffd}\n+the\u{fffd}\n keep\n"));
+    }
+
+    #[test]
+    fn marks_binary_files_unless_literals_are_asked_for() {
+        let from = files(&[("blob.bin", b"\x00\x01")]);
+        let to = files(&[("blob.bin", b"\x00\x02"), ("new.bin", b"\x00")]);
+        let tree = build_diff_tree(&from, &to, 0.75);
+        let patch = build_patch(&tree, &from, &to, PatchOptions::default());
+
+        assert!(!patch.contains("GIT binary patch"));
+        assert!(patch.contains("Binary files a/blob.bin and b/blob.bin differ\n"));
+        assert!(patch.contains("Binary files /dev/null and b/new.bin differ\n"));
+    }
+
+    #[test]
+    fn git_applies_text_binary_added_and_removed_files() {
+        let binary_from: Vec<u8> = (0..=255).cycle().take(3000).collect();
+        let binary_to: Vec<u8> = (0..=255).rev().cycle().take(2000).collect();
+        let from = files(&[
+            ("src/lib.rs", b"fn a() {}\nfn b() {}\n"),
+            ("logo.png", &binary_from),
+            ("old.txt", b"going away\n"),
+            ("blob.bin", b"\x00\x01\x02"),
+        ]);
+        let to = files(&[
+            ("src/lib.rs", b"fn a() {}\nfn c() {}"),
+            ("logo.png", &binary_to),
+            ("new.txt", b"hello\n"),
+            ("data.bin", b"\x00\xff\x00\xfe"),
+        ]);
+        let tree = build_diff_tree(&from, &to, 0.75);
+        let options = PatchOptions {
+            binary_literals: true,
+            ..PatchOptions::default()
+        };
+        let patch = build_patch(&tree, &from, &to, options);
+        assert!(patch.contains("GIT binary patch"));
+
+        let dir = tempfile::tempdir().unwrap();
+        for (path, entry) in &from {
+            let path = dir.path().join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, &entry.bytes).unwrap();
+        }
+        std::fs::write(dir.path().join("changes.patch"), &patch).unwrap();
+
+        let output = match Command::new("git")
+            .args(["apply", "changes.patch"])
+            .current_dir(dir.path())
+            .output()
+        {
+            Ok(output) => output,
+            Err(err) => {
+                eprintln!("skipping: cannot run git: {err}");
+                return;
+            }
+        };
+        assert!(
+            output.status.success(),
+            "git apply failed: {}\n{patch}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+        for (path, entry) in &to {
+            assert_eq!(std::fs::read(dir.path().join(path)).unwrap(), entry.bytes);
+        }
+        for path in from.keys().filter(|path| !to.contains_key(*path)) {
+            assert!(!dir.path().join(path).exists(), "{path} was not removed");
+        }
+    }
+}
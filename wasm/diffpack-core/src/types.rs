@@ -0,0 +1,297 @@
+use std::borrow::Cow;
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DiffStatus {
+    Added,
+    Removed,
+    Modified,
+    Unchanged,
+    Renamed,
+    /// Same content, but the execute, setuid, setgid or sticky bits changed.
+    #[serde(rename = "mode_changed")]
+    ModeChanged,
+    /// A symlink in both versions that points somewhere else.
+    #[serde(rename = "link_changed")]
+    LinkChanged,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FileType {
+    File,
+    Directory,
+    /// Its `bytes` are the link target, the way git stores symlinks, so target
+    /// changes diff like a one-line file.
+    Symlink,
+}
+
+/// Something suspicious about how an archive named an entry. Extraction still
+/// places the entry at its canonical path, but hostile packages rely on exactly
+/// these tricks, so they are reported rather than silently cleaned up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AnomalyKind {
+    /// A leading `/`, `\\` or drive letter (`C:`).
+    Absolute,
+    /// A `..` component, which escapes the extraction directory when it climbs
+    /// past the root.
+    Traversal,
+    /// A NUL byte, which most extractors treat as the end of the name.
+    NulByte,
+    /// Another entry in the same archive resolves to the same path. The last
+    /// one wins, as it would on disk.
+    Duplicate,
+    /// A symlink whose target resolves outside the package. `raw_path` holds
+    /// the target rather than the entry name.
+    #[serde(rename = "link_escape")]
+    LinkEscape,
+    /// A hard link to no file earlier in the archive, extracted as an empty
+    /// file. `raw_path` holds the target.
+    DanglingLink,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PathAnomaly {
+    pub kind: AnomalyKind,
+    /// The entry name exactly as the archive stores it.
+    pub raw_path: String,
+}
+
+/// How the raw bytes of an extracted file should be interpreted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ContentKind {
+    /// Valid UTF-8 with no NUL bytes.
+    Text,
+    /// Contains NUL bytes in the sniffed prefix, the same heuristic git uses.
+    Binary,
+    /// No NUL bytes but not valid UTF-8 (Latin-1, Shift-JIS, ...). Shown lossily
+    /// but compared like a binary file so replacement characters never count as
+    /// changed lines.
+    Unknown,
+    /// Over the per-file extraction limit. The content was never kept: `bytes`
+    /// holds its SHA-256 instead, so changes are still detected.
+    #[serde(rename = "too_large")]
+    TooLarge { size: u64 },
+}
+
+/// Git looks at the first 8000 bytes when deciding whether a blob is binary.
+const BINARY_SNIFF_LEN: usize = 8000;
+
+impl ContentKind {
+    pub fn detect(bytes: &[u8]) -> Self {
+        let sniff = &bytes[..bytes.len().min(BINARY_SNIFF_LEN)];
+        if sniff.contains(&0) {
+            ContentKind::Binary
+        } else if std::str::from_utf8(bytes).is_ok() {
+            ContentKind::Text
+        } else {
+            ContentKind::Unknown
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FileMapEntry {
+    #[serde(rename = "type")]
+    pub file_type: FileType,
+    pub kind: ContentKind,
+    pub bytes: Vec<u8>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub anomalies: Vec<PathAnomaly>,
+    /// Permission bits (`0o7777`), when the archive records them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<u32>,
+    /// Where a symlink points, or for a file extracted from a hard link, the
+    /// entry it shares content with.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub link_target: Option<String>,
+}
+
+/// Read and write bits follow whoever packed the archive (their umask), so only
+/// these count as a mode change.
+pub const SIGNIFICANT_MODE_BITS: u32 = 0o7111;
+
+impl FileMapEntry {
+    pub fn file(bytes: Vec<u8>) -> Self {
+        Self {
+            file_type: FileType::File,
+            kind: ContentKind::detect(&bytes),
+            bytes,
+            anomalies: Vec::new(),
+            mode: None,
+            link_target: None,
+        }
+    }
+
+    pub fn symlink(target: String) -> Self {
+        Self {
+            file_type: FileType::Symlink,
+            kind: ContentKind::Text,
+            bytes: target.clone().into_bytes(),
+            anomalies: Vec::new(),
+            mode: None,
+            link_target: Some(target),
+        }
+    }
+
+    pub fn with_mode(mut self, mode: Option<u32>) -> Self {
+        self.mode = mode.map(|mode| mode & 0o7777);
+        self
+    }
+
+    /// A file over the per-file limit, identified by the SHA-256 of its content.
+    pub fn too_large(size: u64, sha256: Vec<u8>) -> Self {
+        Self {
+            file_type: FileType::File,
+            kind: ContentKind::TooLarge { size },
+            bytes: sha256,
+            anomalies: Vec::new(),
+            mode: None,
+            link_target: None,
+        }
+    }
+
+    pub fn directory() -> Self {
+        Self {
+            file_type: FileType::Directory,
+            kind: ContentKind::Text,
+            bytes: Vec::new(),
+            anomalies: Vec::new(),
+            mode: None,
+            link_target: None,
+        }
+    }
+
+    /// The file's content when it can be line-diffed, `None` for directories,
+    /// binaries and files in an unknown encoding. A symlink's content is its
+    /// target.
+    pub fn text(&self) -> Option<&str> {
+        match (&self.file_type, self.kind) {
+            (FileType::File | FileType::Symlink, ContentKind::Text) => {
+                std::str::from_utf8(&self.bytes).ok()
+            }
+            _ => None,
+        }
+    }
+
+    /// The file's content for display. Unlike [`FileMapEntry::text`] this decodes
+    /// unknown encodings lossily; only true binaries and files too large to keep
+    /// yield `None`.
+    pub fn display_text(&self) -> Option<Cow<'_, str>> {
+        match (&self.file_type, self.kind) {
+            (FileType::File, ContentKind::Binary | ContentKind::TooLarge { .. })
+            | (FileType::Directory, _) => None,
+            (FileType::File | FileType::Symlink, _) => Some(String::from_utf8_lossy(&self.bytes)),
+        }
+    }
+
+    /// Whether two entries hold the same content. A too-large file's `bytes` is
+    /// a digest, so it only equals another too-large file of the same size.
+    pub fn same_content(&self, other: &FileMapEntry) -> bool {
+        self.kind == other.kind && self.bytes == other.bytes
+    }
+
+    /// Size of the file's content, which for a too-large file is not `bytes`.
+    pub fn size(&self) -> u64 {
+        match self.kind {
+            ContentKind::TooLarge { size } => size,
+            _ => self.bytes.len() as u64,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiffFileEntry {
+    pub path: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old_path: Option<String>,
+    #[serde(rename = "type")]
+    pub file_type: FileType,
+    pub status: DiffStatus,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub binary: bool,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub manifest: bool,
+    /// Either version is over the per-file extraction limit, so only whether the
+    /// file changed is known.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub too_large: bool,
+    /// Permission bits in each version, present only when they differ in
+    /// [`SIGNIFICANT_MODE_BITS`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old_mode: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new_mode: Option<u32>,
+    /// Where a symlink points, or which file a hard link copies, in the newer
+    /// version the entry exists in.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub link_target: Option<String>,
+    /// The previous target of a symlink or hard link whose target changed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old_link_target: Option<String>,
+    /// Path anomalies of this entry in either version.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub anomalies: Vec<PathAnomaly>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub added: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub removed: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub children: Option<Vec<DiffFileEntry>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LineTag {
+    Equal,
+    Insert,
+    Delete,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiffLine {
+    pub tag: LineTag,
+    /// 1-based line number in the old file, absent for inserted lines.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old_line: Option<u32>,
+    /// 1-based line number in the new file, absent for deleted lines.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new_line: Option<u32>,
+    /// The line without its terminator.
+    pub content: String,
+    /// The line is the last of its file and has no terminator, so it differs
+    /// from an otherwise identical line that has one.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub no_newline: bool,
+    /// Ranges of `content` that differ from the paired line on the other side,
+    /// in UTF-16 code units so they index JS strings directly.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub inline: Option<Vec<InlineSpan>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InlineSpan {
+    pub start: u32,
+    pub end: u32,
+}
+
+/// A contiguous run of lines, with ranges following unified diff conventions: a
+/// range that covers no lines starts at the line *before* the change.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiffHunk {
+    pub old_start: u32,
+    pub old_lines: u32,
+    pub new_start: u32,
+    pub new_lines: u32,
+    pub lines: Vec<DiffLine>,
+}